@@ -9,7 +9,7 @@ use std::{
 };
 
 use divan::Bencher;
-use histogram::{Histogram, HistogramValue};
+use histogram::Histogram;
 
 #[derive(Clone)]
 struct GoHistogram(Arc<GoHistogramInner>);
@@ -46,7 +46,11 @@ impl GoHistogram {
             .unwrap_or(self.0.buckets.len());
         let shard_idx = self.0.shard_idx_and_count.fetch_add(2, Ordering::Relaxed) & 1;
         let shard = &self.0.shards[shard_idx as usize];
-        f64::atomic_add(&shard.sum, value, Ordering::Relaxed);
+        (shard.sum)
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |sum| {
+                Some((f64::from_bits(sum) + value).to_bits())
+            })
+            .unwrap();
         shard.buckets[bucket_idx].fetch_add(
             1,
             if COUNT {
@@ -121,6 +125,123 @@ fn go_observe_no_count(bencher: Bencher, spin: Option<usize>) {
     );
 }
 
+fn bench_collect(
+    bencher: Bencher,
+    spin: Option<usize>,
+    observe: impl Fn(&Histogram) + Sync,
+    collect: impl Fn(&Histogram),
+) {
+    let histogram = Histogram::new(vec![1.0]);
+    let stop = AtomicBool::new(false);
+    thread::scope(|s| {
+        if let Some(spin) = spin.as_ref() {
+            s.spawn(|| {
+                while !stop.load(Ordering::Relaxed) {
+                    for _ in 0..*spin {
+                        hint::spin_loop();
+                    }
+                    observe(&histogram);
+                }
+            });
+        }
+        bencher.bench_local(|| collect(&histogram));
+        stop.store(true, Ordering::Relaxed);
+    });
+}
+
+#[divan::bench(args = SPIN_LIMITS)]
+fn collect(bencher: Bencher, spin: Option<usize>) {
+    bench_collect(
+        bencher,
+        spin,
+        |histogram| histogram.observe(black_box(1.0)),
+        |histogram| _ = black_box(histogram.collect().1),
+    );
+}
+
+#[cfg(feature = "publish")]
+#[divan::bench(args = SPIN_LIMITS)]
+fn published(bencher: Bencher, spin: Option<usize>) {
+    bench_collect(
+        bencher,
+        spin,
+        |histogram| {
+            histogram.observe(black_box(1.0));
+            histogram.publish();
+        },
+        |histogram| _ = black_box(histogram.published()),
+    );
+}
+
+// compared with and without `rayon` feature, which parallelizes large collections
+#[divan::bench(args = [1 << 10, 1 << 14, 1 << 17, 1 << 20])]
+fn collect_large(bencher: Bencher, bucket_count: u64) {
+    let histogram = Histogram::new((0..bucket_count).collect::<Vec<_>>());
+    histogram.observe_many(0..bucket_count);
+    bencher.bench_local(|| black_box(histogram.collect().0));
+}
+
+#[cfg(feature = "prometheus-client")]
+#[divan::bench(args = [16, 1 << 10])]
+fn encode(bencher: Bencher, bucket_count: u64) {
+    let histogram = Histogram::new((0..bucket_count).collect::<Vec<_>>());
+    histogram.observe_many(0..bucket_count);
+    let mut registry = prometheus_client::registry::Registry::default();
+    registry.register("histogram", "", histogram);
+    let mut output = String::new();
+    bencher.bench_local(|| {
+        output.clear();
+        prometheus_client::encoding::text::encode(&mut output, &registry).unwrap();
+    });
+}
+
+// per-node shards only pay off on multi-socket hosts, as the node lookup costs a syscall
+// (vDSO on most targets)
+#[cfg(feature = "numa")]
+#[divan::bench(args = SPIN_LIMITS)]
+fn numa_observe(bencher: Bencher, spin: Option<usize>) {
+    bench(
+        bencher,
+        spin,
+        histogram::NumaHistogram::new,
+        histogram::NumaHistogram::observe,
+    );
+}
+
+// latency of the first observation into a fresh histogram, whose counters are cold
+#[divan::bench(args = [false, true])]
+fn first_observe(bencher: Bencher, prewarm: bool) {
+    bencher
+        .with_inputs(|| {
+            let histogram = Histogram::new((0..1024).map(f64::from).collect::<Vec<_>>());
+            if prewarm {
+                histogram.prewarm();
+            }
+            histogram
+        })
+        .bench_local_refs(|histogram| histogram.observe(black_box(512.0)));
+}
+
+fn sorted_values() -> (Vec<f64>, Vec<f64>) {
+    let buckets = (0..20).map(|i| f64::from(1 << i)).collect();
+    let values = (0..1000).map(|i| f64::from(i * i)).collect();
+    (buckets, values)
+}
+
+#[divan::bench]
+fn observe_many(bencher: Bencher) {
+    let (buckets, values) = sorted_values();
+    let histogram = Histogram::new(buckets);
+    bencher.bench_local(|| histogram.observe_many(black_box(&values).iter().copied()));
+}
+
+#[divan::bench]
+fn observe_sorted(bencher: Bencher) {
+    let (buckets, values) = sorted_values();
+    let histogram = Histogram::new(buckets);
+    bencher.bench_local(|| histogram.observe_sorted(black_box(&values).iter().copied()));
+}
+
 fn main() {
     divan::main();
 }
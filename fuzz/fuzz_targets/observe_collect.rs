@@ -0,0 +1,45 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use histogram::{Histogram, HistogramBuckets, HistogramValue};
+use libfuzzer_sys::fuzz_target;
+
+#[derive(Debug, Arbitrary)]
+enum Op<V> {
+    Observe(V),
+    Collect,
+}
+
+#[derive(Debug, Arbitrary)]
+enum Input {
+    U64 { buckets: Vec<u64>, ops: Vec<Op<u64>> },
+    F64 { buckets: Vec<f64>, ops: Vec<Op<f64>> },
+}
+
+fn check<B: HistogramBuckets>(histogram: &Histogram<B>, observed: u64, nan: u64) {
+    let (count, _, buckets) = histogram.collect();
+    assert_eq!(count, observed);
+    // NaN observations are counted but not reported in any bucket
+    assert_eq!(count, buckets.map(|(_, c)| c).sum::<u64>() + nan);
+}
+
+fn run<B: HistogramBuckets>(buckets: B, ops: Vec<Op<B::Value>>) {
+    let histogram = Histogram::new(buckets);
+    let (mut observed, mut nan) = (0, 0);
+    for op in ops {
+        match op {
+            Op::Observe(value) => {
+                nan += value.is_nan() as u64;
+                observed += 1;
+                histogram.observe(value);
+            }
+            Op::Collect => check(&histogram, observed, nan),
+        }
+    }
+    check(&histogram, observed, nan);
+}
+
+fuzz_target!(|input: Input| match input {
+    Input::U64 { buckets, ops } => run(buckets, ops),
+    Input::F64 { buckets, ops } => run(buckets, ops),
+});
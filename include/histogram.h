@@ -0,0 +1,35 @@
+/* C API of the `histogram` crate, enabled by its `ffi` feature. */
+#ifndef HISTOGRAM_H
+#define HISTOGRAM_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Opaque histogram, whose observations are thread-safe. */
+typedef struct Histogram Histogram;
+
+/* Returns a new histogram with `len` strictly increasing `boundaries`,
+ * or NULL if they are invalid. */
+Histogram *histogram_new(const double *boundaries, size_t len);
+
+/* Observes `value`, or does nothing if `histogram` is NULL. */
+void histogram_observe(const Histogram *histogram, double value);
+
+/* Collects the histogram, writing `len + 1` bucket counts (the last one being `+Inf`)
+ * into `out_counts`. Returns the count of buckets written, -1 if a pointer is NULL,
+ * or -2 if `out_len` is lower than `len + 1`. */
+intptr_t histogram_collect(const Histogram *histogram, uint64_t *out_counts, size_t out_len,
+                           double *out_sum, uint64_t *out_count);
+
+/* Frees the histogram, or does nothing if `histogram` is NULL. */
+void histogram_free(Histogram *histogram);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* HISTOGRAM_H */
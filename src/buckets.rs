@@ -0,0 +1,424 @@
+use std::{
+    fmt,
+    marker::PhantomData,
+    ops::{Add, Range, Sub},
+};
+
+use crate::HistogramBuckets;
+
+/// Exponential buckets following Prometheus native histogram schemas.
+///
+/// Boundaries are `base^i` for `i` in `min_index..=max_index`, with
+/// `base = 2^(2^-schema)`, so the bucket `i` covers `(base^(i-1), base^i]`.
+/// Zero and negative values fall into the first bucket.
+///
+/// `prometheus-client` has no native histogram support, so these buckets
+/// are encoded as a classic histogram with exponential boundaries.
+#[derive(Debug, Clone, Copy)]
+pub struct NativeBuckets {
+    schema: i8,
+    min_index: i32,
+    max_index: i32,
+}
+
+impl NativeBuckets {
+    /// # Panics
+    ///
+    /// `schema` must be in `-4..=8`, and `min_index <= max_index`.
+    pub fn new(schema: i8, min_index: i32, max_index: i32) -> Self {
+        assert!((-4..=8).contains(&schema), "schema must be in -4..=8");
+        assert!(min_index <= max_index, "empty index range");
+        Self {
+            schema,
+            min_index,
+            max_index,
+        }
+    }
+
+    pub fn schema(&self) -> i8 {
+        self.schema
+    }
+
+    fn boundary(&self, index: i32) -> f64 {
+        (index as f64 * (-self.schema as f64).exp2()).exp2()
+    }
+}
+
+impl HistogramBuckets for NativeBuckets {
+    type Value = f64;
+    fn bucket_index(&self, value: &Self::Value) -> Option<usize> {
+        if value.is_nan() {
+            return None;
+        }
+        let (min, max) = (self.min_index, self.max_index);
+        // floating point approximation, fixed up against the actual boundaries
+        let approx = (value.log2() * (self.schema as f64).exp2()).ceil();
+        let mut index = (approx as i64).clamp(min as i64, max as i64 + 1) as i32;
+        while index > min && *value <= self.boundary(index - 1) {
+            index -= 1;
+        }
+        while index <= max && *value > self.boundary(index) {
+            index += 1;
+        }
+        (index <= max).then(|| (index - min) as usize)
+    }
+    fn values(&self) -> impl Iterator<Item = Self::Value> {
+        (self.min_index..=self.max_index).map(|i| self.boundary(i))
+    }
+}
+
+#[cfg(feature = "unsafe")]
+// SAFETY: `bucket_index` is always lesser than `max_index - min_index + 1`
+unsafe impl crate::TrustedHistogramBuckets for NativeBuckets {}
+
+/// Generators of bucket values, to be passed to [`Histogram::new`](crate::Histogram::new).
+#[derive(Debug)]
+pub enum Buckets {}
+
+impl Buckets {
+    /// Returns boundaries over `range`, denser near the values of the target `quantiles`,
+    /// assuming observations roughly uniformly distributed over `range`.
+    ///
+    /// Boundaries are spaced by a tenth of the range, and down to a thousandth of it around
+    /// each target, so that quantile estimations around targets have a small error.
+    ///
+    /// # Panics
+    ///
+    /// `range` must be finite and non-empty, and `quantiles` in `0.0..=1.0`.
+    pub fn for_quantiles(quantiles: &[f64], range: Range<f64>) -> Vec<f64> {
+        const COARSE_COUNT: u32 = 10;
+        const FINE_OFFSETS: [f64; 3] = [0.001, 0.005, 0.02];
+        assert!(
+            range.start.is_finite() && range.end.is_finite() && range.start < range.end,
+            "range must be finite and non-empty"
+        );
+        let width = range.end - range.start;
+        let coarse = (1..=COARSE_COUNT).map(|i| range.start + width * f64::from(i) / 10.0);
+        let fine = quantiles.iter().flat_map(|&quantile| {
+            assert!((0.0..=1.0).contains(&quantile), "quantile must be in 0..=1");
+            let target = range.start + width * quantile;
+            let offsets = FINE_OFFSETS.iter().flat_map(|offset| [-offset, *offset]);
+            offsets
+                .map(move |offset| target + width * offset)
+                .chain([target])
+        });
+        let mut boundaries = coarse
+            .chain(fine)
+            .filter(|b| *b > range.start && *b <= range.end)
+            .collect::<Vec<_>>();
+        boundaries.sort_by(f64::total_cmp);
+        // targets may fall on coarse boundaries, up to rounding errors
+        boundaries.dedup_by(|b, prev| *b - *prev < width * 1e-9);
+        boundaries
+    }
+}
+
+/// Exponential buckets with boundaries `start * factor^i` for `i` in `0..count`,
+/// computed on the fly without allocation.
+///
+/// Bucket index is computed in constant time using logarithm.
+#[derive(Debug, Clone, Copy)]
+pub struct LogBuckets {
+    start: f64,
+    factor: f64,
+    count: usize,
+}
+
+impl LogBuckets {
+    /// # Panics
+    ///
+    /// `start` must be positive, `factor` greater than 1, and `count` positive.
+    pub fn new(start: f64, factor: f64, count: usize) -> Self {
+        assert!(start > 0.0, "start must be positive");
+        assert!(factor > 1.0, "factor must be greater than 1");
+        assert!(count > 0, "count must be positive");
+        Self {
+            start,
+            factor,
+            count,
+        }
+    }
+
+    fn boundary(&self, index: usize) -> f64 {
+        self.start * self.factor.powi(index as i32)
+    }
+}
+
+impl HistogramBuckets for LogBuckets {
+    type Value = f64;
+    fn bucket_index(&self, value: &Self::Value) -> Option<usize> {
+        if value.is_nan() {
+            return None;
+        }
+        let max = self.count - 1;
+        // floating point approximation, fixed up against the actual boundaries
+        let approx = ((value / self.start).ln() / self.factor.ln()).ceil();
+        let mut index = approx.clamp(0.0, self.count as f64) as usize;
+        while index > 0 && *value <= self.boundary(index - 1) {
+            index -= 1;
+        }
+        while index <= max && *value > self.boundary(index) {
+            index += 1;
+        }
+        (index <= max).then_some(index)
+    }
+    fn values(&self) -> impl Iterator<Item = Self::Value> {
+        (0..self.count).map(|i| self.boundary(i))
+    }
+}
+
+#[cfg(feature = "unsafe")]
+// SAFETY: `bucket_index` is always lesser than `count`
+unsafe impl crate::TrustedHistogramBuckets for LogBuckets {}
+
+/// Buckets of `inner` with every boundary shifted by `offset`, to reuse a bucket
+/// template across ranges.
+///
+/// Values are shifted back before being bucketed by `inner`; with float values,
+/// rounding may thus misplace values extremely close to a boundary.
+#[derive(Debug, Clone, Copy)]
+pub struct OffsetBuckets<B: HistogramBuckets> {
+    inner: B,
+    offset: B::Value,
+}
+
+impl<B: HistogramBuckets> OffsetBuckets<B> {
+    pub fn new(inner: B, offset: B::Value) -> Self {
+        Self { inner, offset }
+    }
+}
+
+impl<B: HistogramBuckets> HistogramBuckets for OffsetBuckets<B>
+where
+    B::Value: Add<Output = B::Value> + Sub<Output = B::Value> + PartialOrd + Clone,
+{
+    type Value = B::Value;
+    const LEN: Option<usize> = B::LEN;
+    const ORDERED: bool = B::ORDERED;
+    fn bucket_index(&self, value: &Self::Value) -> Option<usize> {
+        if *value >= self.offset {
+            return (self.inner).bucket_index(&(value.clone() - self.offset.clone()));
+        }
+        // shifting back would underflow unsigned values, so boundaries are shifted instead
+        self.values().position(|boundary| *value <= boundary)
+    }
+    fn values(&self) -> impl Iterator<Item = Self::Value> {
+        (self.inner.values()).map(|boundary| boundary + self.offset.clone())
+    }
+}
+
+#[cfg(feature = "unsafe")]
+// SAFETY: `bucket_index` is either the one of `inner` or a position in `values`
+unsafe impl<B: crate::TrustedHistogramBuckets> crate::TrustedHistogramBuckets for OffsetBuckets<B> where
+    B::Value: Add<Output = B::Value> + Sub<Output = B::Value> + PartialOrd + Clone
+{
+}
+
+/// Buckets of `inner` with observed values clamped to the range of its bucket values,
+/// so that nothing is counted in the `+Inf` bucket, e.g. for a bounded domain.
+///
+/// The clamped value is the one summed, so the sum is not the one of observed values.
+/// `NaN` values are not clamped.
+#[derive(Debug, Clone, Copy)]
+pub struct ClampBuckets<B: HistogramBuckets> {
+    inner: B,
+    min: B::Value,
+    max: B::Value,
+}
+
+impl<B: HistogramBuckets> ClampBuckets<B>
+where
+    B::Value: Clone,
+{
+    /// # Panics
+    ///
+    /// `inner` must have at least one bucket value.
+    pub fn new(inner: B) -> Self {
+        let mut values = inner.values();
+        let min = values.next().expect("empty buckets");
+        let max = values.last().unwrap_or_else(|| min.clone());
+        Self { inner, min, max }
+    }
+}
+
+impl<B: HistogramBuckets> HistogramBuckets for ClampBuckets<B>
+where
+    B::Value: PartialOrd + Clone,
+{
+    type Value = B::Value;
+    const LEN: Option<usize> = B::LEN;
+    const ORDERED: bool = B::ORDERED;
+    fn bucket_index(&self, value: &Self::Value) -> Option<usize> {
+        self.inner.bucket_index(value)
+    }
+    fn values(&self) -> impl Iterator<Item = Self::Value> {
+        self.inner.values()
+    }
+    fn clamp(&self, value: &Self::Value) -> Option<Self::Value> {
+        if *value < self.min {
+            Some(self.min.clone())
+        } else if *value > self.max {
+            Some(self.max.clone())
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(feature = "unsafe")]
+// SAFETY: `bucket_index` is the one of `inner`
+unsafe impl<B: crate::TrustedHistogramBuckets> crate::TrustedHistogramBuckets for ClampBuckets<B> where
+    B::Value: PartialOrd + Clone
+{
+}
+
+/// Values counted by magnitude in [`AbsBuckets`].
+pub trait Signed: PartialOrd + Clone {
+    /// Absolute value of a negative value, or `None` if it is not negative.
+    ///
+    /// It saturates when not representable, e.g. `i64::MIN` gives `i64::MAX`,
+    /// so that it is counted in the last bucket.
+    fn negative_abs(&self) -> Option<Self>;
+}
+
+impl Signed for i64 {
+    fn negative_abs(&self) -> Option<Self> {
+        (*self < 0).then(|| self.saturating_abs())
+    }
+}
+
+impl Signed for f64 {
+    fn negative_abs(&self) -> Option<Self> {
+        (*self < 0.0).then(|| -self)
+    }
+}
+
+/// Wrapped buckets counting values by magnitude, e.g. for signed errors.
+///
+/// The absolute value is the one summed, so the sum is not the one of observed values.
+/// With [`observe_sorted`](crate::Histogram::observe_sorted), values must be sorted
+/// by absolute value.
+#[derive(Debug, Clone, Copy)]
+pub struct AbsBuckets<B: HistogramBuckets>(pub B);
+
+impl<B: HistogramBuckets> HistogramBuckets for AbsBuckets<B>
+where
+    B::Value: Signed,
+{
+    type Value = B::Value;
+    const LEN: Option<usize> = B::LEN;
+    const ORDERED: bool = B::ORDERED;
+    fn bucket_index(&self, value: &Self::Value) -> Option<usize> {
+        match value.negative_abs() {
+            Some(abs) => self.0.bucket_index(&abs),
+            None => self.0.bucket_index(value),
+        }
+    }
+    fn values(&self) -> impl Iterator<Item = Self::Value> {
+        self.0.values()
+    }
+    fn clamp(&self, value: &Self::Value) -> Option<Self::Value> {
+        let abs = value.negative_abs();
+        self.0.clamp(abs.as_ref().unwrap_or(value)).or(abs)
+    }
+}
+
+#[cfg(feature = "unsafe")]
+// SAFETY: `bucket_index` is the one of `inner`
+unsafe impl<B: crate::TrustedHistogramBuckets> crate::TrustedHistogramBuckets for AbsBuckets<B> where
+    B::Value: Signed
+{
+}
+
+/// Buckets defined by explicit `[start, end)` ranges, exported with their `end` as
+/// bucket value.
+///
+/// A value is counted in the first range containing it, so overlapping ranges are
+/// matched in order; values between ranges are handled like values above every
+/// bucket, see [`OverflowPolicy`](crate::OverflowPolicy). As for other buckets,
+/// range ends must be sorted without duplicates.
+///
+/// Because of gaps and overlaps, [`observe_sorted`](crate::Histogram::observe_sorted)
+/// looks ranges up like [`observe`](crate::Histogram::observe).
+#[derive(Debug, Clone)]
+pub struct RangeBuckets(pub Vec<Range<f64>>);
+
+impl HistogramBuckets for RangeBuckets {
+    type Value = f64;
+    const ORDERED: bool = false;
+    fn bucket_index(&self, value: &Self::Value) -> Option<usize> {
+        self.0.iter().position(|range| range.contains(value))
+    }
+    fn values(&self) -> impl Iterator<Item = Self::Value> {
+        self.0.iter().map(|range| range.end)
+    }
+}
+
+#[cfg(feature = "unsafe")]
+// SAFETY: `bucket_index` is a position in the ranges, which are as many as `values`
+unsafe impl crate::TrustedHistogramBuckets for RangeBuckets {}
+
+/// Categories counted by [`EnumBuckets`], typically a fieldless enum.
+///
+/// The bucket value of a category is its index, and its sum is always zero.
+pub trait Categorical: Copy + 'static {
+    /// Every category, in index order; it must not be empty, which is checked at
+    /// compile time by [`EnumBuckets::new`].
+    const VARIANTS: &'static [Self];
+    /// Index of the category in [`VARIANTS`](Self::VARIANTS), e.g. `*self as usize`.
+    fn index(&self) -> usize;
+}
+
+/// A [`Categorical`] value observed in [`EnumBuckets`].
+///
+/// Categories are wrapped, so that [`HistogramValue`](crate::HistogramValue) is not
+/// implemented for every [`Categorical`] type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Category<E>(pub E);
+
+impl<E: Categorical> From<E> for Category<E> {
+    fn from(category: E) -> Self {
+        Self(category)
+    }
+}
+
+/// One bucket per [`Categorical`] variant, to count observations by category.
+pub struct EnumBuckets<E>(PhantomData<fn() -> E>);
+
+impl<E: Categorical> EnumBuckets<E> {
+    pub const fn new() -> Self {
+        const { assert!(!E::VARIANTS.is_empty(), "`VARIANTS` must not be empty") };
+        Self(PhantomData)
+    }
+}
+
+impl<E: Categorical> Default for EnumBuckets<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E> Clone for EnumBuckets<E> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<E> Copy for EnumBuckets<E> {}
+
+impl<E> fmt::Debug for EnumBuckets<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("EnumBuckets").finish()
+    }
+}
+
+impl<E: Categorical> HistogramBuckets for EnumBuckets<E> {
+    type Value = Category<E>;
+    const LEN: Option<usize> = Some(E::VARIANTS.len());
+    fn bucket_index(&self, value: &Self::Value) -> Option<usize> {
+        Some(value.0.index())
+    }
+    fn values(&self) -> impl Iterator<Item = Self::Value> {
+        E::VARIANTS.iter().copied().map(Category)
+    }
+}
@@ -0,0 +1,77 @@
+use std::time::Duration;
+
+use crate::{Histogram, HistogramBuckets, LeFormat, Options, OverflowPolicy};
+
+/// Builder of a [`Histogram`], returned by [`Histogram::builder`].
+#[derive(Debug)]
+#[must_use = "use `build` to create the histogram"]
+pub struct HistogramBuilder<B> {
+    buckets: B,
+    options: Options,
+}
+
+impl<B: HistogramBuckets> HistogramBuilder<B> {
+    pub fn new(buckets: B) -> Self {
+        Self {
+            buckets,
+            options: Options::default(),
+        }
+    }
+
+    /// See [`Histogram::with_le_format`].
+    ///
+    /// It is ignored by `prometheus-client` encoding, see [`LeFormat`].
+    pub fn le_format(mut self, le_format: LeFormat) -> Self {
+        self.options.le_format = le_format;
+        self
+    }
+
+    /// See [`Histogram::with_overflow_policy`].
+    pub fn overflow_policy(mut self, overflow_policy: OverflowPolicy) -> Self {
+        self.options.overflow_policy = overflow_policy;
+        self
+    }
+
+    /// See [`Histogram::with_max_buckets`].
+    pub fn max_buckets(mut self, max_buckets: usize) -> Self {
+        self.options.max_buckets = max_buckets;
+        self
+    }
+
+    /// Number of attempts to read a shard before waiting for in-flight observations
+    /// to complete, 10 by default.
+    ///
+    /// Zero makes collections always wait, which saves CPU when observations are often
+    /// in flight, at the cost of latency.
+    pub fn spin_limit(mut self, spin_limit: usize) -> Self {
+        self.options.spin_limit = spin_limit;
+        self
+    }
+
+    /// Whether to track the sum, `true` by default.
+    ///
+    /// An untracked sum is always zero, and saves an atomic operation per observation.
+    /// The setting is checked at runtime, so every observation, tracked or not, pays a
+    /// branch on it; the `f64-no-sum` feature removes the `f64` sum at compile time instead.
+    pub fn track_sum(mut self, track_sum: bool) -> Self {
+        self.options.track_sum = track_sum;
+        self
+    }
+
+    /// Minimum interval between collections, none by default.
+    ///
+    /// [`collect`](Histogram::collect) and its variants return the previous result without
+    /// swapping shards if it is more recent than `min_interval`, e.g. when scraped more often
+    /// than observed. Resets are never paced.
+    pub fn min_collect_interval(mut self, min_interval: Duration) -> Self {
+        self.options.min_collect_interval = Some(min_interval);
+        self
+    }
+
+    /// # Panics
+    ///
+    /// See [`Histogram::new`].
+    pub fn build(self) -> Histogram<B> {
+        Histogram::with_options(self.buckets, self.options)
+    }
+}
@@ -0,0 +1,29 @@
+use crate::{AtomicCounter, HistogramValue, Ordering};
+
+/// Fixed-point amount in hundredths, e.g. cents of a currency.
+///
+/// The sum is accumulated exactly in cents, and only converted to units when collected.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Cents(pub i64);
+
+impl HistogramValue for Cents {
+    const HAS_NAN: bool = false;
+    fn into_f64(self) -> f64 {
+        self.0 as f64 / 100.0
+    }
+    fn is_nan(&self) -> bool {
+        false
+    }
+    fn atomic_add(counter: &AtomicCounter, value: Self, ordering: Ordering) {
+        i64::atomic_add(counter, value.0, ordering);
+    }
+    fn atomic_add_ref(counter: &AtomicCounter, value: &Self, ordering: Ordering) {
+        Self::atomic_add(counter, *value, ordering);
+    }
+    fn from_bits(bits: u64) -> Self {
+        Self(i64::from_bits(bits))
+    }
+    fn from_f64(value: f64) -> Self {
+        Self((value * 100.0).round() as i64)
+    }
+}
@@ -0,0 +1,89 @@
+//! C API over `Histogram<Vec<f64>>`, declared in `include/histogram.h`.
+//!
+//! Histograms are opaque pointers, which can be shared between threads for observations.
+
+use std::{ptr, slice};
+
+use crate::Histogram;
+
+/// Returns a new histogram, or null if `boundaries` is null or the buckets are invalid,
+/// see [`Histogram::try_new`].
+///
+/// # Safety
+///
+/// `boundaries` must point to `len` values.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn histogram_new(boundaries: *const f64, len: usize) -> *mut Histogram {
+    if boundaries.is_null() {
+        return ptr::null_mut();
+    }
+    // SAFETY: `boundaries` is non-null and points to `len` values
+    let boundaries = unsafe { slice::from_raw_parts(boundaries, len) };
+    match Histogram::try_new(boundaries.to_vec()) {
+        Ok(histogram) => Box::into_raw(Box::new(histogram)),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Observes `value`, or does nothing if `histogram` is null.
+///
+/// # Safety
+///
+/// `histogram` must have been returned by [`histogram_new`] and not freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn histogram_observe(histogram: *const Histogram, value: f64) {
+    // SAFETY: `histogram` is either null or valid
+    if let Some(histogram) = unsafe { histogram.as_ref() } {
+        histogram.observe(value);
+    }
+}
+
+/// Collects the histogram, writing bucket counts, including the `+Inf` one, into
+/// `out_counts`, and returns the count of buckets written, or `-1` if a pointer is null,
+/// or `-2` if `out_len` is lower than the bucket count (without collecting).
+///
+/// # Safety
+///
+/// `histogram` must have been returned by [`histogram_new`] and not freed, `out_counts`
+/// must point to `out_len` writable values, and `out_sum` and `out_count` must be writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn histogram_collect(
+    histogram: *const Histogram,
+    out_counts: *mut u64,
+    out_len: usize,
+    out_sum: *mut f64,
+    out_count: *mut u64,
+) -> isize {
+    if out_counts.is_null() || out_sum.is_null() || out_count.is_null() {
+        return -1;
+    }
+    // SAFETY: `histogram` is either null or valid
+    let Some(histogram) = (unsafe { histogram.as_ref() }) else {
+        return -1;
+    };
+    let bucket_count = histogram.0.boundaries.len() + 1;
+    if out_len < bucket_count {
+        return -2;
+    }
+    let (count, sum, counts) = histogram.collect_counts();
+    // SAFETY: pointers are non-null and writable, `out_counts` for at least `bucket_count`
+    unsafe {
+        slice::from_raw_parts_mut(out_counts, bucket_count).copy_from_slice(&counts);
+        out_sum.write(sum);
+        out_count.write(count);
+    }
+    bucket_count as isize
+}
+
+/// Frees the histogram, or does nothing if `histogram` is null.
+///
+/// # Safety
+///
+/// `histogram` must have been returned by [`histogram_new`] and not already freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn histogram_free(histogram: *mut Histogram) {
+    if !histogram.is_null() {
+        // SAFETY: `histogram` has been allocated by `histogram_new`
+        drop(unsafe { Box::from_raw(histogram) });
+    }
+}
@@ -0,0 +1,38 @@
+use crate::{Histogram, HistogramBuckets, HistogramSnapshot};
+
+/// A set of histograms observing the same values, e.g. a global histogram and
+/// a per-endpoint one.
+///
+/// Observations are not atomic across the group: each member is updated in turn.
+#[derive(Debug, Clone)]
+pub struct HistogramGroup<B: HistogramBuckets = Vec<f64>> {
+    histograms: Vec<Histogram<B>>,
+}
+
+impl<B: HistogramBuckets> HistogramGroup<B> {
+    pub fn new(histograms: impl IntoIterator<Item = Histogram<B>>) -> Self {
+        Self {
+            histograms: histograms.into_iter().collect(),
+        }
+    }
+
+    pub fn observe(&self, value: B::Value) {
+        self.observe_ref(&value);
+    }
+
+    pub fn observe_ref(&self, value: &B::Value) {
+        for histogram in &self.histograms {
+            histogram.observe_ref(value);
+        }
+    }
+
+    pub fn histograms(&self) -> &[Histogram<B>] {
+        &self.histograms
+    }
+
+    /// Returns the snapshot of each member, in the group order.
+    #[must_use]
+    pub fn snapshots(&self) -> Vec<HistogramSnapshot> {
+        self.histograms.iter().map(Histogram::snapshot).collect()
+    }
+}
@@ -0,0 +1,66 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use crate::{Histogram, HistogramBuckets};
+
+/// A set of histograms sharing the same buckets, identified by labels.
+#[derive(Debug)]
+pub struct HistogramVec<L, B: HistogramBuckets = Vec<f64>> {
+    buckets: B,
+    pub(crate) series: RwLock<HashMap<L, Histogram<B>>>,
+}
+
+impl<L: Eq + Hash + Clone, B: HistogramBuckets + Clone> HistogramVec<L, B> {
+    pub fn new(buckets: B) -> Self {
+        Self::with_capacity(buckets, 0)
+    }
+
+    pub fn with_capacity(buckets: B, capacity: usize) -> Self {
+        Self {
+            buckets,
+            series: RwLock::new(HashMap::with_capacity(capacity)),
+        }
+    }
+
+    pub fn get_or_create(&self, labels: &L) -> Histogram<B> {
+        if let Some(histogram) = self.read().get(labels) {
+            return histogram.clone();
+        }
+        let mut series = self.write();
+        let histogram = series
+            .entry(labels.clone())
+            .or_insert_with(|| Histogram::new(self.buckets.clone()));
+        histogram.clone()
+    }
+
+    /// Removes the series, which is recreated empty on the next
+    /// [`get_or_create`](Self::get_or_create).
+    pub fn remove(&self, labels: &L) -> Option<Histogram<B>> {
+        self.write().remove(labels)
+    }
+
+    pub fn len(&self) -> usize {
+        self.read().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.read().capacity()
+    }
+
+    // Series are only inserted or removed at once, so a thread panicking while holding the
+    // lock cannot leave the map inconsistent: poisoning is ignored.
+    fn read(&self) -> RwLockReadGuard<'_, HashMap<L, Histogram<B>>> {
+        self.series.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, HashMap<L, Histogram<B>>> {
+        self.series.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
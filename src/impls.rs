@@ -1,5 +1,12 @@
-use super::{Arc, AtomicU64, Ordering};
-use crate::{HistogramBuckets, HistogramValue};
+use std::{
+    collections::BTreeSet,
+    num::NonZeroU64,
+    ops::{Range, RangeInclusive},
+    time::Duration,
+};
+
+use super::{Arc, AtomicCounter, Ordering};
+use crate::{Categorical, Category, HistogramBuckets, HistogramValue};
 
 impl HistogramValue for u64 {
     const HAS_NAN: bool = false;
@@ -9,12 +16,102 @@ impl HistogramValue for u64 {
     fn is_nan(&self) -> bool {
         false
     }
-    fn atomic_add(counter: &AtomicU64, value: Self, ordering: Ordering) {
+    fn atomic_add(counter: &AtomicCounter, value: Self, ordering: Ordering) {
         counter.fetch_add(value, ordering);
     }
+    fn atomic_add_ref(counter: &AtomicCounter, value: &Self, ordering: Ordering) {
+        Self::atomic_add(counter, *value, ordering);
+    }
     fn from_bits(bits: u64) -> Self {
         bits
     }
+    fn from_f64(value: f64) -> Self {
+        value as u64
+    }
+    // wraps like repeated additions
+    fn atomic_add_weighted(counter: &AtomicCounter, value: &Self, weight: u64, ordering: Ordering) {
+        Self::atomic_add(counter, value.wrapping_mul(weight), ordering);
+    }
+}
+
+// the sum is split into two counters, the carry of the low one being added to the high one
+impl HistogramValue for u128 {
+    const HAS_NAN: bool = false;
+    const WIDE_SUM: bool = true;
+    fn into_f64(self) -> f64 {
+        self as f64
+    }
+    fn is_nan(&self) -> bool {
+        false
+    }
+    // only the low counter is available, histograms use `atomic_add_wide` instead
+    fn atomic_add(counter: &AtomicCounter, value: Self, ordering: Ordering) {
+        counter.fetch_add(value as u64, ordering);
+    }
+    fn atomic_add_ref(counter: &AtomicCounter, value: &Self, ordering: Ordering) {
+        Self::atomic_add(counter, *value, ordering);
+    }
+    fn from_bits(bits: u64) -> Self {
+        bits.into()
+    }
+    fn from_f64(value: f64) -> Self {
+        value as u128
+    }
+    fn atomic_add_wide(
+        low: &AtomicCounter,
+        high: &AtomicCounter,
+        value: &Self,
+        ordering: Ordering,
+    ) {
+        let (value_low, value_high) = (*value as u64, (*value >> u64::BITS) as u64);
+        let carry = low
+            .fetch_add(value_low, ordering)
+            .overflowing_add(value_low)
+            .1;
+        let value_high = value_high.wrapping_add(carry.into());
+        if value_high != 0 {
+            high.fetch_add(value_high, ordering);
+        }
+    }
+    fn wide_sum_from_bits(low: u64, high: u64) -> f64 {
+        ((u128::from(high) << u64::BITS) | u128::from(low)) as f64
+    }
+    fn atomic_add_wide_weighted(
+        low: &AtomicCounter,
+        high: &AtomicCounter,
+        value: &Self,
+        weight: u64,
+        ordering: Ordering,
+    ) {
+        let value = value.wrapping_mul(weight.into());
+        Self::atomic_add_wide(low, high, &value, ordering);
+    }
+}
+
+impl HistogramValue for i64 {
+    const HAS_NAN: bool = false;
+    fn into_f64(self) -> f64 {
+        self as f64
+    }
+    fn is_nan(&self) -> bool {
+        false
+    }
+    fn atomic_add(counter: &AtomicCounter, value: Self, ordering: Ordering) {
+        // two's complement addition is the same for signed and unsigned integers
+        counter.fetch_add(value as u64, ordering);
+    }
+    fn atomic_add_ref(counter: &AtomicCounter, value: &Self, ordering: Ordering) {
+        Self::atomic_add(counter, *value, ordering);
+    }
+    fn from_bits(bits: u64) -> Self {
+        bits as i64
+    }
+    fn from_f64(value: f64) -> Self {
+        value as i64
+    }
+    fn atomic_add_weighted(counter: &AtomicCounter, value: &Self, weight: u64, ordering: Ordering) {
+        u64::atomic_add_weighted(counter, &(*value as u64), weight, ordering);
+    }
 }
 
 impl HistogramValue for f64 {
@@ -25,24 +122,176 @@ impl HistogramValue for f64 {
     fn is_nan(&self) -> bool {
         f64::is_nan(*self)
     }
-    fn atomic_add(counter: &AtomicU64, value: Self, ordering: Ordering) {
+    // an overflowing shard sum becomes `+Inf` (or `-Inf`), and stays so until reset
+    #[cfg(not(feature = "f64-no-sum"))]
+    fn atomic_add(counter: &AtomicCounter, value: Self, ordering: Ordering) {
         counter
             .fetch_update(ordering, Ordering::Relaxed, |c| {
                 Some(f64::to_bits(f64::from_bits(c) + value))
             })
             .unwrap();
     }
+    // the sum is not tracked, so observations involve no floating point arithmetic
+    #[cfg(feature = "f64-no-sum")]
+    fn atomic_add(_counter: &AtomicCounter, _value: Self, _ordering: Ordering) {}
+    fn atomic_add_ref(counter: &AtomicCounter, value: &Self, ordering: Ordering) {
+        Self::atomic_add(counter, *value, ordering);
+    }
+    #[cfg(feature = "f64-no-sum")]
+    fn atomic_sub_bits(_counter: &AtomicCounter, _bits: u64, _ordering: Ordering) {}
+    #[cfg(not(feature = "f64-no-sum"))]
+    fn atomic_sub_bits(counter: &AtomicCounter, bits: u64, ordering: Ordering) {
+        // an unchanged sum is zeroed, as non-finite sums cannot be subtracted
+        counter
+            .fetch_update(ordering, Ordering::Relaxed, |c| {
+                Some(match c == bits {
+                    true => 0.0f64.to_bits(),
+                    false => f64::to_bits(f64::from_bits(c) - f64::from_bits(bits)),
+                })
+            })
+            .unwrap();
+    }
+    fn atomic_add_bits(counter: &AtomicCounter, bits: u64, ordering: Ordering) {
+        Self::atomic_add(counter, f64::from_bits(bits), ordering);
+    }
+    // rounded once, instead of once per addition
+    fn atomic_add_weighted(counter: &AtomicCounter, value: &Self, weight: u64, ordering: Ordering) {
+        Self::atomic_add(counter, value * weight as f64, ordering);
+    }
     fn from_bits(bits: u64) -> Self {
         f64::from_bits(bits)
     }
+    fn from_f64(value: f64) -> Self {
+        value
+    }
+}
+
+// the sum is the count of `true`, which cannot be represented by a `bool`;
+// `[false, true]` buckets count each outcome separately
+impl HistogramValue for bool {
+    const HAS_NAN: bool = false;
+    fn into_f64(self) -> f64 {
+        self as u64 as f64
+    }
+    fn is_nan(&self) -> bool {
+        false
+    }
+    fn atomic_add(counter: &AtomicCounter, value: Self, ordering: Ordering) {
+        counter.fetch_add(value as u64, ordering);
+    }
+    fn atomic_add_ref(counter: &AtomicCounter, value: &Self, ordering: Ordering) {
+        Self::atomic_add(counter, *value, ordering);
+    }
+    fn from_bits(bits: u64) -> Self {
+        bits != 0
+    }
+    fn from_f64(value: f64) -> Self {
+        value != 0.0
+    }
+    fn sum_from_bits(bits: u64) -> f64 {
+        bits as f64
+    }
+    fn atomic_add_sum(counter: &AtomicCounter, sum: f64, ordering: Ordering) {
+        counter.fetch_add(sum as u64, ordering);
+    }
+}
+
+// the sum is accumulated like `u64` one, but zero, e.g. an empty sum, cannot be represented
+// by a `NonZeroU64`, so sums are never converted back to it
+impl HistogramValue for NonZeroU64 {
+    const HAS_NAN: bool = false;
+    fn into_f64(self) -> f64 {
+        self.get().into_f64()
+    }
+    fn is_nan(&self) -> bool {
+        false
+    }
+    fn atomic_add(counter: &AtomicCounter, value: Self, ordering: Ordering) {
+        u64::atomic_add(counter, value.get(), ordering);
+    }
+    fn atomic_add_ref(counter: &AtomicCounter, value: &Self, ordering: Ordering) {
+        Self::atomic_add(counter, *value, ordering);
+    }
+    fn from_bits(bits: u64) -> Self {
+        NonZeroU64::new(bits).unwrap_or(NonZeroU64::MIN)
+    }
+    fn from_f64(value: f64) -> Self {
+        Self::from_bits(u64::from_f64(value))
+    }
+    fn sum_from_bits(bits: u64) -> f64 {
+        u64::sum_from_bits(bits)
+    }
+    fn atomic_add_sum(counter: &AtomicCounter, sum: f64, ordering: Ordering) {
+        u64::atomic_add_sum(counter, sum, ordering);
+    }
+}
+
+// categories have no meaningful sum, so it stays at zero, i.e. the first variant index,
+// which exists as `EnumBuckets` requires `VARIANTS` to be non-empty
+impl<E: Categorical> HistogramValue for Category<E> {
+    const HAS_NAN: bool = false;
+    fn into_f64(self) -> f64 {
+        self.0.index() as f64
+    }
+    fn is_nan(&self) -> bool {
+        false
+    }
+    fn atomic_add(_counter: &AtomicCounter, _value: Self, _ordering: Ordering) {}
+    fn atomic_add_ref(_counter: &AtomicCounter, _value: &Self, _ordering: Ordering) {}
+    fn from_bits(_bits: u64) -> Self {
+        Self(E::VARIANTS[0])
+    }
+    fn from_f64(_value: f64) -> Self {
+        Self(E::VARIANTS[0])
+    }
+}
+
+// the sum is accumulated in nanoseconds to avoid floating point drift,
+// and converted to seconds only when collected
+impl HistogramValue for Duration {
+    const HAS_NAN: bool = false;
+    fn into_f64(self) -> f64 {
+        self.as_secs_f64()
+    }
+    fn is_nan(&self) -> bool {
+        false
+    }
+    fn atomic_add(counter: &AtomicCounter, value: Self, ordering: Ordering) {
+        counter.fetch_add(value.as_nanos() as u64, ordering);
+    }
+    fn atomic_add_ref(counter: &AtomicCounter, value: &Self, ordering: Ordering) {
+        Self::atomic_add(counter, *value, ordering);
+    }
+    fn from_bits(bits: u64) -> Self {
+        Duration::from_nanos(bits)
+    }
+    // saturates instead of panicking, as imported sums are not validated
+    fn from_f64(value: f64) -> Self {
+        Duration::try_from_secs_f64(value).unwrap_or(match value > 0.0 {
+            true => Duration::MAX,
+            false => Duration::ZERO,
+        })
+    }
+}
+
+pub(crate) fn slice_bucket_index<V: PartialOrd>(buckets: &[V], value: &V) -> Option<usize> {
+    // signed zeros compare equal, so `-0.0` and `0.0` always share the same bucket
+    match buckets {
+        // unrolled comparisons for the common case of a few buckets
+        [b0] => (value <= b0).then_some(0),
+        [b0, _] if value <= b0 => Some(0),
+        [_, b1] => (value <= b1).then_some(1),
+        _ => buckets.iter().position(|b| value <= b),
+    }
 }
 
 macro_rules! impl_buckets {
     ($($(@$N:ident)? $ty:ty),* $(,)?) => {$(
         impl<V: HistogramValue + PartialOrd + Clone + 'static, $(const $N: usize)?> HistogramBuckets for $ty {
             type Value = V;
+            $(const LEN: Option<usize> = Some($N);)?
             fn bucket_index(&self, value: &Self::Value) -> Option<usize> {
-                self.iter().position(|b| value <= b)
+                slice_bucket_index(&self[..], value)
             }
             fn values(&self) -> impl Iterator<Item = Self::Value> {
                 self.iter().cloned()
@@ -55,16 +304,114 @@ macro_rules! impl_buckets {
 }
 impl_buckets!(&[V], Vec<V>, Box<[V]>, Arc<[V]>, @N [V; N], @N &[V; N]);
 
+// not in `impl_buckets`, as the inline capacity is not the length
+#[cfg(feature = "smallvec")]
+impl<V: HistogramValue + PartialOrd + Clone + 'static, const N: usize> HistogramBuckets
+    for smallvec::SmallVec<[V; N]>
+{
+    type Value = V;
+    fn bucket_index(&self, value: &Self::Value) -> Option<usize> {
+        slice_bucket_index(&self[..], value)
+    }
+    fn values(&self) -> impl Iterator<Item = Self::Value> {
+        self.iter().cloned()
+    }
+}
+#[cfg(all(feature = "smallvec", feature = "unsafe"))]
+// SAFETY: `len` is constant and `bucket_index` is always in bounds
+unsafe impl<V: HistogramValue + PartialOrd + Clone + 'static, const N: usize>
+    crate::TrustedHistogramBuckets for smallvec::SmallVec<[V; N]>
+{
+}
+
+impl<V: HistogramValue + Ord + Clone + 'static> HistogramBuckets for BTreeSet<V> {
+    type Value = V;
+    fn bucket_index(&self, value: &Self::Value) -> Option<usize> {
+        // there is no rank query on `BTreeSet`, so lower boundaries are counted
+        let index = self.range(..value).count();
+        (index < self.len()).then_some(index)
+    }
+    fn values(&self) -> impl Iterator<Item = Self::Value> {
+        self.iter().cloned()
+    }
+}
+#[cfg(feature = "unsafe")]
+// SAFETY: `len` is constant and `bucket_index` is always in bounds
+unsafe impl<V: HistogramValue + Ord + Clone + 'static> crate::TrustedHistogramBuckets
+    for BTreeSet<V>
+{
+}
+
+/// One bucket per integer, with values lower than the start falling into the first bucket.
+impl HistogramBuckets for Range<u64> {
+    type Value = u64;
+    fn bucket_index(&self, value: &Self::Value) -> Option<usize> {
+        (*value < self.end).then(|| value.saturating_sub(self.start) as usize)
+    }
+    fn values(&self) -> impl Iterator<Item = Self::Value> {
+        self.clone()
+    }
+}
+#[cfg(feature = "unsafe")]
+// SAFETY: `bucket_index` is always lesser than `end - start`, as `value < end`
+unsafe impl crate::TrustedHistogramBuckets for Range<u64> {}
+
+/// One bucket per integer, with values lower than the start falling into the first bucket.
+impl HistogramBuckets for RangeInclusive<u64> {
+    type Value = u64;
+    fn bucket_index(&self, value: &Self::Value) -> Option<usize> {
+        (!self.is_empty() && value <= self.end())
+            .then(|| value.saturating_sub(*self.start()) as usize)
+    }
+    fn values(&self) -> impl Iterator<Item = Self::Value> {
+        self.clone()
+    }
+}
+#[cfg(feature = "unsafe")]
+// SAFETY: `bucket_index` is always lesser than `end - start + 1`, as `value <= end`
+unsafe impl crate::TrustedHistogramBuckets for RangeInclusive<u64> {}
+
 #[cfg(not(any(feature = "unsafe", feature = "naive")))]
 mod aligned {
-    use std::iter;
+    #[cfg(feature = "cache-line-64")]
+    use std::ops::Deref;
+    use std::{alloc::Layout, iter};
 
-    use crossbeam_utils::CachePadded;
+    #[cfg(not(feature = "cache-line-64"))]
+    use crossbeam_utils::CachePadded as CacheLine;
 
-    use super::AtomicU64;
+    use super::AtomicCounter;
     use crate::HistogramCounters;
 
-    const COUNTERS_PER_CACHE_LINE: usize = align_of::<CachePadded<()>>() / align_of::<AtomicU64>();
+    // `CachePadded` uses 128 bytes on targets prefetching adjacent cache lines, so counters
+    // can be packed in 64 bytes instead, halving memory at the cost of more false sharing
+    #[cfg(feature = "cache-line-64")]
+    #[derive(Debug, Default)]
+    #[repr(align(64))]
+    struct CacheLine<T>(T);
+
+    #[cfg(feature = "cache-line-64")]
+    impl<T> Deref for CacheLine<T> {
+        type Target = T;
+
+        fn deref(&self) -> &Self::Target {
+            &self.0
+        }
+    }
+
+    // on targets where cache line alignment is smaller than `AtomicU64` one, counters
+    // are padded individually
+    pub(crate) const COUNTERS_PER_CACHE_LINE: usize =
+        match align_of::<CacheLine<()>>() / align_of::<AtomicCounter>() {
+            0 => 1,
+            n => n,
+        };
+    const _: () = assert!(COUNTERS_PER_CACHE_LINE >= 1);
+
+    // const so that it is computed at compile time for buckets with a constant length
+    const fn cache_lines(bucket_count: usize) -> usize {
+        (2 + bucket_count).div_ceil(COUNTERS_PER_CACHE_LINE)
+    }
 
     // all counters should be stored on the same cache line to optimize grouped atomic operations
     // they are stored in the following order:
@@ -72,28 +419,42 @@ mod aligned {
     // - _sum
     // - _buckets
     #[derive(Debug)]
-    pub(crate) struct Counters(Vec<CachePadded<[AtomicU64; COUNTERS_PER_CACHE_LINE]>>);
+    pub(crate) struct Counters(Vec<CacheLine<[AtomicCounter; COUNTERS_PER_CACHE_LINE]>>);
+
+    impl Counters {
+        // `_count` and `_sum` may not share the same cache line if it holds a single counter,
+        // constant indexes are optimized otherwise
+        fn counter(&self, idx: usize) -> &AtomicCounter {
+            &self.0[idx / COUNTERS_PER_CACHE_LINE][idx % COUNTERS_PER_CACHE_LINE]
+        }
+    }
 
     impl HistogramCounters for Counters {
         fn new(bucket_count: usize) -> Self {
-            let cache_lines =
-                (/* _count + _sum */2 + bucket_count).div_ceil(COUNTERS_PER_CACHE_LINE);
+            let cache_lines = cache_lines(bucket_count);
             let vec = iter::repeat_with(Default::default)
                 .take(cache_lines)
                 .collect();
             Self(vec)
         }
-        fn count(&self) -> &AtomicU64 {
-            &self.0[0][0]
+        fn size(bucket_count: usize) -> Option<usize> {
+            let lines = bucket_count
+                .checked_add(2)?
+                .div_ceil(COUNTERS_PER_CACHE_LINE);
+            let layout =
+                Layout::array::<CacheLine<[AtomicCounter; COUNTERS_PER_CACHE_LINE]>>(lines);
+            Some(layout.ok()?.size())
         }
-        fn sum(&self) -> &AtomicU64 {
-            &self.0[0][1]
+        fn count(&self) -> &AtomicCounter {
+            self.counter(0)
         }
-        fn bucket(&self, bucket_index: usize) -> &AtomicU64 {
-            let idx = bucket_index + 2;
-            &self.0[idx / COUNTERS_PER_CACHE_LINE][idx % COUNTERS_PER_CACHE_LINE]
+        fn sum(&self) -> &AtomicCounter {
+            self.counter(1)
+        }
+        fn bucket(&self, bucket_index: usize) -> &AtomicCounter {
+            self.counter(bucket_index + 2)
         }
-        fn buckets(&self, bucket_count: usize) -> impl Iterator<Item = &AtomicU64> {
+        fn buckets(&self, bucket_count: usize) -> impl Iterator<Item = &AtomicCounter> {
             self.0
                 .iter()
                 .flat_map(|cache_line| cache_line.iter())
@@ -105,16 +466,16 @@ mod aligned {
 
 #[cfg(all(feature = "naive", not(feature = "unsafe")))]
 mod naive {
-    use std::iter;
+    use std::{alloc::Layout, iter};
 
-    use super::AtomicU64;
+    use super::AtomicCounter;
     use crate::HistogramCounters;
 
     #[derive(Debug)]
     pub(crate) struct Counters {
-        count: AtomicU64,
-        sum: AtomicU64,
-        buckets: Vec<AtomicU64>,
+        count: AtomicCounter,
+        sum: AtomicCounter,
+        buckets: Vec<AtomicCounter>,
     }
 
     #[cfg(feature = "naive")]
@@ -128,16 +489,20 @@ mod naive {
                     .collect(),
             }
         }
-        fn count(&self) -> &AtomicU64 {
+        fn size(bucket_count: usize) -> Option<usize> {
+            let layout = Layout::array::<AtomicCounter>(bucket_count.checked_add(2)?);
+            Some(layout.ok()?.size())
+        }
+        fn count(&self) -> &AtomicCounter {
             &self.count
         }
-        fn sum(&self) -> &AtomicU64 {
+        fn sum(&self) -> &AtomicCounter {
             &self.sum
         }
-        fn bucket(&self, bucket_index: usize) -> &AtomicU64 {
+        fn bucket(&self, bucket_index: usize) -> &AtomicCounter {
             &self.buckets[bucket_index]
         }
-        fn buckets(&self, bucket_count: usize) -> impl Iterator<Item = &AtomicU64> {
+        fn buckets(&self, bucket_count: usize) -> impl Iterator<Item = &AtomicCounter> {
             self.buckets[..bucket_count].iter()
         }
     }
@@ -150,14 +515,22 @@ mod r#unsafe {
         alloc::{alloc_zeroed, handle_alloc_error, Layout, LayoutError},
         slice,
     };
+    #[cfg(feature = "allocator-api2")]
+    use std::{fmt, ptr::NonNull, sync::Arc};
 
+    #[cfg(feature = "allocator-api2")]
+    use allocator_api2::alloc::Allocator;
     use crossbeam_utils::CachePadded;
 
-    use super::AtomicU64;
-    use crate::HistogramCounters;
+    use super::AtomicCounter;
+    use crate::{HistogramCounters, HistogramError};
 
     #[derive(Debug)]
-    pub(crate) struct Counters(*const UnsafeCountersInner);
+    pub(crate) struct Counters {
+        inner: *const UnsafeCountersInner,
+        #[cfg(feature = "allocator-api2")]
+        alloc: Option<SharedAllocator>,
+    }
 
     // SAFETY: raw pointer access is properly synchronized
     unsafe impl Send for Counters {}
@@ -165,77 +538,147 @@ mod r#unsafe {
     // SAFETY: raw pointer access is properly synchronized
     unsafe impl Sync for Counters {}
 
+    #[cfg(feature = "allocator-api2")]
+    #[derive(Clone)]
+    pub(crate) struct SharedAllocator(pub(crate) Arc<dyn Allocator + Send + Sync>);
+
+    #[cfg(feature = "allocator-api2")]
+    impl fmt::Debug for SharedAllocator {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("SharedAllocator").finish_non_exhaustive()
+        }
+    }
+
     #[repr(C)]
     struct UnsafeCountersInner {
         _align: CachePadded<()>,
-        count: AtomicU64,
-        sum: AtomicU64,
-        buckets: [AtomicU64; 0],
+        count: AtomicCounter,
+        sum: AtomicCounter,
+        buckets: [AtomicCounter; 0],
     }
 
     impl Counters {
         fn layout(bucket_count: usize) -> Result<Layout, LayoutError> {
-            let buckets_layout = Layout::array::<AtomicU64>(bucket_count)?;
+            let buckets_layout = Layout::array::<AtomicCounter>(bucket_count)?;
             let (layout, _) = Layout::new::<UnsafeCountersInner>().extend(buckets_layout)?;
             Ok(layout)
         }
 
-        fn buckets_ptr(&self) -> *const AtomicU64 {
+        fn checked_layout(bucket_count: usize) -> Layout {
+            let Ok(layout) = Self::layout(bucket_count) else {
+                panic!("{}", HistogramError::CapacityOverflow);
+            };
+            layout
+        }
+
+        fn init(inner: *mut u8, bucket_count: usize) -> *const UnsafeCountersInner {
+            let _ = bucket_count;
+            #[cfg(loom)]
+            for i in 0..bucket_count + 2 {
+                unsafe {
+                    inner
+                        .cast::<AtomicCounter>()
+                        .add(i)
+                        .write(AtomicCounter::new(0))
+                };
+            }
+            inner.cast_const().cast()
+        }
+
+        #[cfg(feature = "allocator-api2")]
+        pub(crate) fn new_in(bucket_count: usize, alloc: SharedAllocator) -> Self {
+            let layout = Self::checked_layout(bucket_count);
+            let Ok(inner) = alloc.0.allocate_zeroed(layout) else {
+                handle_alloc_error(layout);
+            };
+            Self {
+                inner: Self::init(inner.as_ptr().cast(), bucket_count),
+                alloc: Some(alloc),
+            }
+        }
+
+        fn buckets_ptr(&self) -> *const AtomicCounter {
             // Pointer has been properly initialized in `Self::new`
-            unsafe { &raw const (*self.0).buckets }.cast()
+            unsafe { &raw const (*self.inner).buckets }.cast()
         }
     }
 
     impl HistogramCounters for Counters {
         fn new(bucket_count: usize) -> Self {
-            let Ok(layout) = Self::layout(bucket_count) else {
-                panic!("capacity overflow");
-            };
+            let layout = Self::checked_layout(bucket_count);
             // SAFETY: layout has non-zero size
             let inner = unsafe { alloc_zeroed(layout) };
             if inner.is_null() {
                 handle_alloc_error(layout);
             }
-            #[cfg(loom)]
-            for i in 0..bucket_count + 2 {
-                unsafe { inner.cast::<AtomicU64>().add(i).write(AtomicU64::new(0)) };
+            Self {
+                inner: Self::init(inner, bucket_count),
+                #[cfg(feature = "allocator-api2")]
+                alloc: None,
             }
-            Self(inner.cast_const().cast())
         }
 
-        fn count(&self) -> &AtomicU64 {
+        fn size(bucket_count: usize) -> Option<usize> {
+            Some(Self::layout(bucket_count).ok()?.size())
+        }
+
+        fn count(&self) -> &AtomicCounter {
             // SAFETY: UnsafeCountersInner has been allocated and properly zero-initialized
-            unsafe { &(*self.0).count }
+            unsafe { &(*self.inner).count }
         }
 
-        fn sum(&self) -> &AtomicU64 {
+        fn sum(&self) -> &AtomicCounter {
             // SAFETY: UnsafeCountersInner has been allocated and properly zero-initialized
-            unsafe { &(*self.0).sum }
+            unsafe { &(*self.inner).sum }
         }
 
-        fn bucket(&self, bucket_index: usize) -> &AtomicU64 {
+        fn bucket(&self, bucket_index: usize) -> &AtomicCounter {
             // SAFETY: UnsafeCountersInner has been allocated with an extended capacity of
             // `bucket_count`, is properly zero-initialized, and `bucket_index < bucket_count`
             unsafe { &*self.buckets_ptr().add(bucket_index) }
         }
 
-        fn buckets(&self, bucket_count: usize) -> impl Iterator<Item = &AtomicU64> {
+        fn buckets(&self, bucket_count: usize) -> impl Iterator<Item = &AtomicCounter> {
             // SAFETY: UnsafeCountersInner has been allocated with an extended capacity of
             // `bucket_count` and is properly zero-initialized
             unsafe { slice::from_raw_parts(self.buckets_ptr(), bucket_count) }.iter()
         }
 
+        fn reallocate(&mut self, old_bucket_count: usize, bucket_count: usize) {
+            // allocated before freeing, so that `self` stays valid if allocation panics
+            #[cfg(feature = "allocator-api2")]
+            let counters = match self.alloc.clone() {
+                Some(alloc) => Self::new_in(bucket_count, alloc),
+                None => Self::new(bucket_count),
+            };
+            #[cfg(not(feature = "allocator-api2"))]
+            let counters = Self::new(bucket_count);
+            HistogramCounters::drop(self, old_bucket_count);
+            *self = counters;
+        }
+
         fn drop(&mut self, bucket_count: usize) {
             let layout = Self::layout(bucket_count).unwrap();
-            // SAFETY: `self.0` was allocated with the same layout derived from `bucket_count`
-            unsafe { alloc::dealloc(self.0.cast_mut().cast(), layout) }
+            #[cfg(feature = "allocator-api2")]
+            if let Some(alloc) = &self.alloc {
+                let ptr = NonNull::new(self.inner.cast_mut().cast()).unwrap();
+                // SAFETY: `self.inner` was allocated by `alloc` with the same layout
+                // derived from `bucket_count`
+                return unsafe { alloc.0.deallocate(ptr, layout) };
+            }
+            // SAFETY: `self.inner` was allocated with the same layout derived from `bucket_count`
+            unsafe { alloc::dealloc(self.inner.cast_mut().cast(), layout) }
         }
     }
 }
 
 #[cfg(not(any(feature = "unsafe", feature = "naive")))]
 pub(crate) use aligned::Counters;
+#[cfg(all(test, not(any(feature = "unsafe", feature = "naive"))))]
+pub(crate) use aligned::COUNTERS_PER_CACHE_LINE;
 #[cfg(all(feature = "naive", not(feature = "unsafe")))]
 pub(crate) use naive::Counters;
 #[cfg(feature = "unsafe")]
 pub(crate) use r#unsafe::Counters;
+#[cfg(feature = "allocator-api2")]
+pub(crate) use r#unsafe::SharedAllocator;
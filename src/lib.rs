@@ -1,55 +1,282 @@
-#![cfg_attr(not(any(feature = "unsafe", feature = "asm")), forbid(unsafe_code))]
+#![cfg_attr(not(any(feature = "unsafe", feature = "asm")), deny(unsafe_code))]
+// `ffi` and `numa` only allow unsafe code in their own items
+#![cfg_attr(
+    not(any(feature = "unsafe", feature = "asm", feature = "ffi", feature = "numa")),
+    forbid(unsafe_code)
+)]
 
+#[cfg(all(not(loom), feature = "separate-waiting-flag"))]
+use std::sync::atomic::AtomicBool;
+#[cfg(all(not(loom), not(feature = "portable-atomic")))]
+use std::sync::atomic::AtomicU64;
 #[cfg(not(loom))]
-use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Mutex, MutexGuard,
+};
 use std::{
-    array,
-    fmt::Error,
+    array, error, fmt,
     future::poll_fn,
     iter,
     marker::PhantomData,
-    mem,
-    sync::{Arc, Mutex},
-    task::Poll,
+    ops::Deref,
+    sync::{Arc, PoisonError, TryLockError},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+#[cfg(feature = "prometheus-client")]
+use std::{cell::Cell, fmt::Error};
+#[cfg(not(loom))]
+use std::{
+    hint,
+    task::{Wake, Waker},
+    thread,
 };
 
 #[cfg(not(loom))]
 use futures_executor::block_on;
 #[cfg(not(loom))]
 use futures_util::task::AtomicWaker;
+#[cfg(all(loom, feature = "separate-waiting-flag"))]
+use loom::sync::atomic::AtomicBool;
 #[cfg(loom)]
 use loom::{
     future::{block_on, AtomicWaker},
-    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    hint,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Mutex, MutexGuard,
+    },
 };
+#[cfg(all(not(loom), feature = "portable-atomic"))]
+use portable_atomic::AtomicU64;
 #[cfg(feature = "prometheus-client")]
 use prometheus_client::{
     encoding::{EncodeMetric, MetricEncoder, NoLabelSet},
     metrics::{MetricType, TypedMetric},
 };
 
+mod buckets;
+mod builder;
+mod cents;
+#[cfg(feature = "ffi")]
+#[allow(unsafe_code)]
+mod ffi;
+mod histogram_group;
+mod histogram_vec;
 mod impls;
+mod macros;
+#[cfg(feature = "numa")]
+mod numa;
+#[cfg(feature = "raw-atomics")]
+mod raw;
+mod sampled;
+#[cfg(feature = "sketch")]
+mod sketch;
+mod snapshot;
 #[cfg(test)]
 mod tests;
+#[cfg(any(test, feature = "testutil"))]
+mod testutil;
+mod text;
+mod timer;
+#[cfg(feature = "tracing")]
+mod trace;
+
+pub use buckets::{
+    AbsBuckets, Buckets, Categorical, Category, ClampBuckets, EnumBuckets, LogBuckets,
+    NativeBuckets, OffsetBuckets, RangeBuckets, Signed,
+};
+pub use builder::HistogramBuilder;
+pub use cents::Cents;
+pub use histogram_group::HistogramGroup;
+pub use histogram_vec::HistogramVec;
+#[cfg(feature = "numa")]
+pub use numa::NumaHistogram;
+pub use ordered_float::OrderedFloat;
+#[cfg(feature = "raw-atomics")]
+pub use raw::RawHandle;
+pub use sampled::SampledHistogram;
+#[cfg(feature = "sketch")]
+pub use sketch::SketchedHistogram;
+pub use snapshot::{
+    Collected, CollectedFull, DecodeError, HistogramSnapshot, IncompatibleBoundaries,
+};
+#[cfg(any(test, feature = "testutil"))]
+pub use testutil::assert_histogram;
+pub use text::{LeFormat, ParseError};
+pub use timer::{Clock, HistogramTimer, SystemClock};
+
+/// Atomic 64-bit counter passed to [`HistogramValue`] methods.
+///
+/// It wraps the standard `AtomicU64`, or [`portable_atomic::AtomicU64`] with the
+/// `portable-atomic` feature, so that enabling the feature doesn't change the trait.
+///
+/// [`portable_atomic::AtomicU64`]: https://docs.rs/portable-atomic
+#[derive(Debug, Default)]
+#[repr(transparent)]
+pub struct AtomicCounter(AtomicU64);
+
+impl AtomicCounter {
+    pub fn new(value: u64) -> Self {
+        Self(AtomicU64::new(value))
+    }
+
+    #[inline]
+    pub fn load(&self, ordering: Ordering) -> u64 {
+        self.0.load(ordering)
+    }
+
+    #[inline]
+    pub fn store(&self, value: u64, ordering: Ordering) {
+        self.0.store(value, ordering);
+    }
+
+    #[inline]
+    pub fn fetch_add(&self, value: u64, ordering: Ordering) -> u64 {
+        self.0.fetch_add(value, ordering)
+    }
+
+    #[inline]
+    pub fn fetch_sub(&self, value: u64, ordering: Ordering) -> u64 {
+        self.0.fetch_sub(value, ordering)
+    }
+
+    #[inline]
+    pub fn fetch_update(
+        &self,
+        set_ordering: Ordering,
+        fetch_ordering: Ordering,
+        f: impl FnMut(u64) -> Option<u64>,
+    ) -> Result<u64, u64> {
+        self.0.fetch_update(set_ordering, fetch_ordering, f)
+    }
+
+    #[cfg(not(feature = "separate-waiting-flag"))]
+    #[inline]
+    fn fetch_or(&self, value: u64, ordering: Ordering) -> u64 {
+        self.0.fetch_or(value, ordering)
+    }
+
+    #[cfg(not(feature = "separate-waiting-flag"))]
+    #[inline]
+    fn fetch_and(&self, value: u64, ordering: Ordering) -> u64 {
+        self.0.fetch_and(value, ordering)
+    }
+}
 
 pub trait HistogramValue {
     const HAS_NAN: bool;
+    /// Whether the sum spans a second, high-order, counter, for values wider than 64 bits.
+    const WIDE_SUM: bool = false;
     fn into_f64(self) -> f64;
     fn is_nan(&self) -> bool;
-    fn atomic_add(counter: &AtomicU64, value: Self, ordering: Ordering);
+    fn atomic_add(counter: &AtomicCounter, value: Self, ordering: Ordering);
+    fn atomic_add_ref(counter: &AtomicCounter, value: &Self, ordering: Ordering);
     fn from_bits(bits: u64) -> Self;
+    fn from_f64(value: f64) -> Self;
+    /// Subtracts a sum previously read from `counter`, in its raw bits representation.
+    fn atomic_sub_bits(counter: &AtomicCounter, bits: u64, ordering: Ordering) {
+        counter.fetch_sub(bits, ordering);
+    }
+    /// Adds a sum read from a counter, in its raw bits representation, to `counter`.
+    fn atomic_add_bits(counter: &AtomicCounter, bits: u64, ordering: Ordering) {
+        counter.fetch_add(bits, ordering);
+    }
+    /// Adds `value` observed `weight` times to `counter`, instead of `weight` calls to
+    /// [`atomic_add_ref`](Self::atomic_add_ref).
+    ///
+    /// The default implementation doubles the value in a local counter with
+    /// [`atomic_add_bits`](Self::atomic_add_bits), in `O(log(weight))` additions.
+    fn atomic_add_weighted(counter: &AtomicCounter, value: &Self, weight: u64, ordering: Ordering) {
+        let (multiple, sum) = (AtomicCounter::new(0), AtomicCounter::new(0));
+        Self::atomic_add_ref(&multiple, value, Ordering::Relaxed);
+        let mut weight = weight;
+        while weight != 0 {
+            let bits = multiple.load(Ordering::Relaxed);
+            if weight & 1 != 0 {
+                Self::atomic_add_bits(&sum, bits, Ordering::Relaxed);
+            }
+            Self::atomic_add_bits(&multiple, bits, Ordering::Relaxed);
+            weight >>= 1;
+        }
+        Self::atomic_add_bits(counter, sum.load(Ordering::Relaxed), ordering);
+    }
+    /// Converts a sum read from a counter, in its raw bits representation, into `f64`.
+    fn sum_from_bits(bits: u64) -> f64
+    where
+        Self: Sized,
+    {
+        Self::from_bits(bits).into_f64()
+    }
+    /// Adds a sum expressed in [`into_f64`](Self::into_f64) unit to `counter`.
+    fn atomic_add_sum(counter: &AtomicCounter, sum: f64, ordering: Ordering)
+    where
+        Self: Sized,
+    {
+        Self::atomic_add(counter, Self::from_f64(sum), ordering);
+    }
+    /// Adds `value` to a sum split into `low` and `high` counters, used instead of
+    /// [`atomic_add_ref`](Self::atomic_add_ref) if [`WIDE_SUM`](Self::WIDE_SUM) is set.
+    fn atomic_add_wide(
+        low: &AtomicCounter,
+        high: &AtomicCounter,
+        value: &Self,
+        ordering: Ordering,
+    ) {
+        let _ = high;
+        Self::atomic_add_ref(low, value, ordering);
+    }
+    /// Same as [`atomic_add_weighted`](Self::atomic_add_weighted), but for a sum split like
+    /// in [`atomic_add_wide`](Self::atomic_add_wide).
+    fn atomic_add_wide_weighted(
+        low: &AtomicCounter,
+        high: &AtomicCounter,
+        value: &Self,
+        weight: u64,
+        ordering: Ordering,
+    ) {
+        let _ = high;
+        Self::atomic_add_weighted(low, value, weight, ordering);
+    }
+    /// Converts a sum split into `low` and `high` counters, in their raw bits representation,
+    /// into `f64`.
+    fn wide_sum_from_bits(low: u64, high: u64) -> f64
+    where
+        Self: Sized,
+    {
+        let _ = high;
+        Self::sum_from_bits(low)
+    }
 }
 
 pub trait HistogramBuckets {
     type Value: HistogramValue;
+    /// Count of [`values`](Self::values), if it is constant, e.g. for arrays, so that the
+    /// bucket count is computed at compile time.
+    ///
+    /// If `Some`, it must be equal to the count of `values`, which is only checked in debug
+    /// builds; counters are otherwise sized with it.
+    const LEN: Option<usize> = None;
+    /// Whether [`bucket_index`](Self::bucket_index) returns the first bucket value greater
+    /// than or equal to the value, like [`Vec`] buckets, so that
+    /// [`Histogram::observe_sorted`] can replace lookup by a cursor.
+    const ORDERED: bool = true;
     fn bucket_index(&self, value: &Self::Value) -> Option<usize>;
     fn values(&self) -> impl Iterator<Item = Self::Value>;
+    /// Value to count and sum instead of an observed one, e.g. to bound it,
+    /// or `None` to keep the observed value.
+    fn clamp(&self, value: &Self::Value) -> Option<Self::Value> {
+        let _ = value;
+        None
+    }
 }
 #[cfg(feature = "unsafe")]
 /// # Safety
 ///
 /// [`HistogramBuckets::bucket_index`] must return an index lesser than
-/// the count of items returned by [`HistogramBuckets::values`]
+/// the count of items returned by [`HistogramBuckets::values`], and
+/// [`HistogramBuckets::LEN`], if `Some`, must be equal to this count.
 pub unsafe trait TrustedHistogramBuckets: HistogramBuckets {}
 
 #[derive(Debug)]
@@ -58,20 +285,166 @@ pub struct Histogram<B: HistogramBuckets = Vec<f64>, const TRUSTED_BUCKETS: bool
 );
 
 impl<B: HistogramBuckets> Histogram<B> {
+    /// # Panics
+    ///
+    /// There must be at most [`MAX_BUCKETS`] bucket values.
     pub fn new(buckets: B) -> Self {
-        let bucket_count =
-            buckets.values().count() + /* inf */ 1 + /* nan */ B::Value::HAS_NAN as usize;
+        Self::with_options(buckets, Options::default())
+    }
+
+    /// Same as [`new`](Self::new), but validates the buckets instead of accepting them as is.
+    ///
+    /// Bucket values must be non-empty, sorted, without duplicates and not `NaN`.
+    pub fn try_new(buckets: B) -> Result<Self, HistogramError> {
+        let len = B::LEN.unwrap_or_else(|| buckets.values().count());
+        let size = (len.checked_add(2))
+            .and_then(|_| <impls::Counters as HistogramCounters>::size(Self::bucket_count(len)));
+        if size.is_none() {
+            return Err(HistogramError::CapacityOverflow);
+        }
+        if len > MAX_BUCKETS {
+            return Err(HistogramError::TooManyBuckets);
+        }
+        let values = buckets.values().map(B::Value::into_f64).collect::<Vec<_>>();
+        if values.is_empty() {
+            return Err(HistogramError::EmptyBuckets);
+        }
+        if values.iter().any(|v| v.is_nan()) {
+            return Err(HistogramError::NanBoundary);
+        }
+        if values.windows(2).any(|w| w[0] >= w[1]) {
+            return Err(HistogramError::UnsortedBuckets);
+        }
+        Ok(Self::new(buckets))
+    }
+
+    /// Same as [`new`](Self::new), but with the [`LeFormat`] of
+    /// [`write_text`](Self::write_text); `prometheus-client` encoding ignores it.
+    pub fn with_le_format(buckets: B, le_format: LeFormat) -> Self {
+        Self::with_options(
+            buckets,
+            Options {
+                le_format,
+                ..Default::default()
+            },
+        )
+    }
+
+    pub fn with_overflow_policy(buckets: B, overflow_policy: OverflowPolicy) -> Self {
+        Self::with_options(
+            buckets,
+            Options {
+                overflow_policy,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Same as [`new`](Self::new), but with a custom limit instead of [`MAX_BUCKETS`].
+    pub fn with_max_buckets(buckets: B, max_buckets: usize) -> Self {
+        Self::with_options(
+            buckets,
+            Options {
+                max_buckets,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Same as [`new`](Self::new), but counters are allocated with the given allocator.
+    #[cfg(feature = "allocator-api2")]
+    pub fn new_in<A>(buckets: B, alloc: A) -> Self
+    where
+        A: allocator_api2::alloc::Allocator + Send + Sync + 'static,
+    {
+        let alloc = impls::SharedAllocator(Arc::new(alloc));
+        Self::with_counters(buckets, Options::default(), |bucket_count| {
+            impls::Counters::new_in(bucket_count, alloc.clone())
+        })
+    }
+
+    /// Returns a builder to configure the histogram with several options.
+    pub fn builder(buckets: B) -> HistogramBuilder<B> {
+        HistogramBuilder::new(buckets)
+    }
+
+    fn with_options(buckets: B, options: Options) -> Self {
+        Self::with_counters(buckets, options, HistogramCounters::new)
+    }
+
+    fn with_counters(
+        buckets: B,
+        options: Options,
+        new_counters: impl Fn(usize) -> impls::Counters,
+    ) -> Self {
+        let len = Self::len(&buckets);
+        if len > options.max_buckets {
+            let max = options.max_buckets;
+            panic!("{}: {len} > {max}", HistogramError::TooManyBuckets);
+        }
+        let bucket_count = Self::BUCKET_COUNT.unwrap_or_else(|| Self::bucket_count(len));
+        let boundaries = buckets.values().map(B::Value::into_f64).collect();
         Self(Arc::new(HistogramInner {
             buckets,
+            boundaries,
             bucket_count,
             hot_shard: AtomicUsize::new(0),
-            shards: array::from_fn(|_| Shard::new(bucket_count)),
-            collector: Mutex::new(()),
-            waker: AtomicWaker::new(),
+            shards: array::from_fn(|_| Shard::new(new_counters(bucket_count))),
+            generation: AtomicU64::new(0),
+            reset_epoch: AtomicU64::new(0),
+            collector: Mutex::new(CollectorState::default()),
+            waker: CollectorWaker::new(),
+            options,
+            #[cfg(feature = "publish")]
+            published: Default::default(),
         }))
     }
 }
 
+/// Default maximum number of bucket values, guarding against accidental huge allocations,
+/// e.g. with boundaries coming from configuration.
+///
+/// Each bucket value costs 16 bytes of counters (one per shard), or more with the default
+/// cache-padded backend.
+pub const MAX_BUCKETS: usize = 1 << 24;
+
+#[derive(Debug, Clone, Copy)]
+struct Options {
+    le_format: LeFormat,
+    overflow_policy: OverflowPolicy,
+    max_buckets: usize,
+    spin_limit: usize,
+    track_sum: bool,
+    min_collect_interval: Option<Duration>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            le_format: LeFormat::default(),
+            overflow_policy: OverflowPolicy::default(),
+            max_buckets: MAX_BUCKETS,
+            spin_limit: SPIN_LOOP_LIMIT,
+            track_sum: true,
+            min_collect_interval: None,
+        }
+    }
+}
+
+/// Handling of observations above every bucket value (`NaN` excluded).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Observations are counted in the `+Inf` bucket.
+    #[default]
+    CountInf,
+    /// Observations are dropped, and thus not counted in the count and the sum either,
+    /// as the count must remain the sum of the bucket counts.
+    Drop,
+    /// Observations are counted in the last bucket, or in the `+Inf` bucket if
+    /// there is no bucket.
+    Saturate,
+}
+
 #[cfg(feature = "unsafe")]
 impl<B: TrustedHistogramBuckets> Histogram<B, true> {
     pub fn new_trusted(buckets: B) -> Self {
@@ -80,37 +453,775 @@ impl<B: TrustedHistogramBuckets> Histogram<B, true> {
 }
 
 impl<B: HistogramBuckets, const TRUSTED_BUCKETS: bool> Histogram<B, TRUSTED_BUCKETS> {
+    pub(crate) const BUCKET_COUNT: Option<usize> = match B::LEN {
+        Some(len) => Some(Self::bucket_count(len)),
+        None => None,
+    };
+
+    fn len(buckets: &B) -> usize {
+        let Some(len) = B::LEN else {
+            return buckets.values().count();
+        };
+        debug_assert_eq!(
+            len,
+            buckets.values().count(),
+            "`HistogramBuckets::LEN` must be the count of `HistogramBuckets::values`"
+        );
+        len
+    }
+
+    const fn bucket_count(len: usize) -> usize {
+        len + /* inf */ 1 + /* nan */ B::Value::HAS_NAN as usize
+    }
+
     pub fn observe(&self, value: B::Value) {
-        let buckets = &self.0.buckets;
-        let fallback_bucket =
-            || self.0.bucket_count - 1 - (B::Value::HAS_NAN && !value.is_nan()) as usize;
-        let bucket_index = buckets.bucket_index(&value).unwrap_or_else(fallback_bucket);
-        #[cfg(feature = "unsafe")]
+        self.observe_ref(&value);
+    }
+
+    /// Same as [`observe`](Self::observe), but does nothing on `None`, e.g. for missing samples.
+    pub fn observe_opt(&self, value: Option<B::Value>) {
+        if let Some(value) = value {
+            self.observe_ref(&value);
+        }
+    }
+
+    /// Same as [`observe`](Self::observe), but does nothing if `cond` is `false`,
+    /// e.g. for sampled observations.
+    pub fn observe_if(&self, cond: bool, value: B::Value) {
+        if cond {
+            self.observe_ref(&value);
+        }
+    }
+
+    /// Same as [`observe`](Self::observe), but avoids moving non-`Copy` values.
+    pub fn observe_ref(&self, value: &B::Value) {
+        let clamped = self.0.buckets.clamp(value);
+        let value = clamped.as_ref().unwrap_or(value);
+        if let Some(bucket_index) = self.bucket_index(value) {
+            self.observe_in_bucket(value, bucket_index);
+        }
+    }
+
+    /// Same as [`observe_many`](Self::observe_many), but each value is observed `weight`
+    /// times, e.g. to add pre-aggregated data.
+    ///
+    /// Values are added to a single shard, and the count and the sum are only updated once
+    /// at the end, so a concurrent collection waits for the whole batch. The sum is
+    /// accumulated like with [`observe`](Self::observe), e.g. exactly for integers.
+    pub fn observe_many_weighted(&self, pairs: impl IntoIterator<Item = (B::Value, u64)>) {
+        let shard = &self.0.shards[self.0.hot_shard.load(Ordering::Relaxed)];
+        // local counters, added to the shard ones at the end
+        let (sum, sum_high) = (AtomicCounter::new(0), AtomicCounter::new(0));
+        let mut count = 0u64;
+        for (value, weight) in pairs {
+            if weight == 0 {
+                continue;
+            }
+            let value = self.0.buckets.clamp(&value).unwrap_or(value);
+            let Some(bucket_index) = self.bucket_index(&value) else {
+                continue;
+            };
+            self.check_bucket_index(bucket_index);
+            shard.add_to_bucket(bucket_index, weight);
+            match (self.0.options.track_sum, B::Value::WIDE_SUM) {
+                (false, _) => {}
+                (true, true) => {
+                    let ordering = Ordering::Relaxed;
+                    B::Value::atomic_add_wide_weighted(&sum, &sum_high, &value, weight, ordering);
+                }
+                (true, false) => {
+                    B::Value::atomic_add_weighted(&sum, &value, weight, Ordering::Relaxed);
+                }
+            }
+            count = count.saturating_add(weight);
+        }
+        let (low, high) = (
+            sum.load(Ordering::Relaxed),
+            sum_high.load(Ordering::Relaxed),
+        );
+        shard.add_sum_bits((u128::from(high) << u64::BITS) | u128::from(low));
+        shard.add_count(count, &self.0.waker);
+    }
+
+    // `None` if the observation must be dropped
+    fn bucket_index(&self, value: &B::Value) -> Option<usize> {
+        match self.0.buckets.bucket_index(value) {
+            Some(bucket_index) => Some(bucket_index),
+            None if B::Value::HAS_NAN && value.is_nan() => Some(self.0.bucket_count - 1),
+            None => self.overflow_bucket(),
+        }
+    }
+
+    // `None` if the observation must be dropped
+    fn overflow_bucket(&self) -> Option<usize> {
+        let inf_bucket = self.0.bucket_count - 1 - B::Value::HAS_NAN as usize;
+        match self.0.options.overflow_policy {
+            OverflowPolicy::CountInf => Some(inf_bucket),
+            OverflowPolicy::Drop => None,
+            OverflowPolicy::Saturate => Some(inf_bucket.saturating_sub(1)),
+        }
+    }
+
+    fn observe_in_bucket(&self, value: &B::Value, bucket_index: usize) {
+        self.check_bucket_index(bucket_index);
+        self.observe_in_hot_shard(value, bucket_index);
+    }
+
+    fn check_bucket_index(&self, bucket_index: usize) {
+        // also checked without `unsafe`, as an out-of-range index would otherwise either panic
+        // without context or, with aligned counters, silently land in the cache line padding
         if !TRUSTED_BUCKETS {
-            assert!(bucket_index < self.0.bucket_count);
+            assert!(
+                bucket_index < self.0.bucket_count,
+                "bucket index {bucket_index} out of range, `HistogramBuckets::bucket_index` \
+                 must be lesser than the count of `HistogramBuckets::values`"
+            );
         }
+    }
+
+    /// Same as [`observe`](Self::observe), but with the bucket index computed by the caller,
+    /// e.g. once for many identical values.
+    ///
+    /// # Safety
+    ///
+    /// `bucket_index` must be lesser than the count of bucket values plus one for the `+Inf`
+    /// bucket, plus one for the `NaN` bucket if [`HistogramValue::HAS_NAN`].
+    ///
+    /// The observation is otherwise counted as is, so a valid index which is not the bucket
+    /// of `value` makes the histogram inconsistent, although not unsound.
+    #[cfg(feature = "unsafe")]
+    pub unsafe fn observe_unchecked(&self, bucket_index: usize, value: B::Value) {
+        self.observe_in_hot_shard(&value, bucket_index);
+    }
+
+    fn observe_in_hot_shard(&self, value: &B::Value, bucket_index: usize) {
         let hot_shard = self.0.hot_shard.load(Ordering::Relaxed);
         #[cfg(feature = "unsafe")]
         if hot_shard > 1 {
             unsafe { std::hint::unreachable_unchecked() }
         }
-        self.0.shards[hot_shard].observe(value, bucket_index, &self.0.waker);
+        let shard = &self.0.shards[hot_shard];
+        shard.observe(value, bucket_index, self.0.options.track_sum, &self.0.waker);
+    }
+
+    pub fn observe_many(&self, values: impl IntoIterator<Item = B::Value>) {
+        for value in values {
+            self.observe(value);
+        }
+    }
+
+    pub fn observe_all(&self, values: &[B::Value]) {
+        for value in values {
+            self.observe_ref(value);
+        }
+    }
+
+    /// Same as [`observe_many`](Self::observe_many), but values must be sorted in ascending
+    /// order, so bucket lookup is replaced by a cursor advancing along the buckets.
+    ///
+    /// Unsorted values are counted in wrong buckets, which is checked in debug builds.
+    /// Buckets not [`ORDERED`](HistogramBuckets::ORDERED) fall back to
+    /// [`observe_many`](Self::observe_many).
+    pub fn observe_sorted(&self, values: impl IntoIterator<Item = B::Value>)
+    where
+        B::Value: PartialOrd + Clone,
+    {
+        if !B::ORDERED {
+            return self.observe_many(values);
+        }
+        let nan_bucket = self.0.bucket_count - 1;
+        let inf_bucket = nan_bucket - B::Value::HAS_NAN as usize;
+        let mut boundaries = self.0.buckets.values().peekable();
+        let mut bucket_index = 0;
+        let mut previous: Option<B::Value> = None;
+        for value in values {
+            let value = self.0.buckets.clamp(&value).unwrap_or(value);
+            if value.is_nan() {
+                self.observe_in_bucket(&value, nan_bucket);
+                continue;
+            }
+            debug_assert!(
+                previous
+                    .replace(value.clone())
+                    .is_none_or(|prev| prev <= value),
+                "values must be sorted"
+            );
+            while boundaries.next_if(|b| value > *b).is_some() {
+                bucket_index += 1;
+            }
+            if bucket_index < inf_bucket {
+                self.observe_in_bucket(&value, bucket_index);
+            } else if let Some(bucket_index) = self.overflow_bucket() {
+                self.observe_in_bucket(&value, bucket_index);
+            }
+        }
     }
 
+    /// Returns the count, the sum, and the bucket counts of the histogram.
+    ///
+    /// The sum is converted with [`HistogramValue::into_f64`], whatever the way
+    /// it is accumulated, e.g. [`Duration`] sum is accumulated
+    /// in nanoseconds but returned in seconds.
+    #[must_use = "use `rotate` to swap shards without reading them"]
     pub fn collect(&self) -> (u64, f64, impl Iterator<Item = (f64, u64)>) {
-        let _guard = self.0.collector.lock().unwrap();
-        let hot_shard = self.0.hot_shard.load(Ordering::Relaxed);
-        let cold_shard = hot_shard ^ 1;
-        let (count_cold, sum_cold, buckets_cold) =
-            self.0.shards[cold_shard].collect(self.0.bucket_count, &self.0.waker);
-        self.0.hot_shard.store(cold_shard, Ordering::Relaxed);
-        let (count_hot, sum_hot, buckets_hot) =
-            self.0.shards[hot_shard].collect(self.0.bucket_count, &self.0.waker);
-        let buckets = (self.0.buckets.values().map(B::Value::into_f64))
+        let (count, sum, buckets, _) = self.collect_with_stats();
+        (count, sum, buckets)
+    }
+
+    /// Same as [`collect`](Self::collect), but also returns [`CollectStats`].
+    #[must_use = "use `rotate` to swap shards without reading them"]
+    pub fn collect_with_stats(&self) -> (u64, f64, impl Iterator<Item = (f64, u64)>, CollectStats) {
+        let mut state = self.lock_collector();
+        self.collect_locked(&mut state)
+    }
+
+    /// Same as [`collect`](Self::collect), but returns bucket counts without boundaries,
+    /// for callers already knowing them.
+    ///
+    /// Counts are in the order of bucket values, followed by the `+Inf` bucket count.
+    #[must_use = "use `rotate` to swap shards without reading them"]
+    pub fn collect_counts(&self) -> (u64, f64, Vec<u64>) {
+        let raw = {
+            let mut state = self.lock_collector();
+            self.collect_raw_paced(&mut state)
+        };
+        let mut counts = raw.counts;
+        // the `NaN` bucket is not exposed, like in `collect`
+        counts.truncate(self.0.boundaries.len() + 1);
+        (raw.count, raw.sum, counts)
+    }
+
+    /// Same as [`collect`](Self::collect), but only returns the count and the sum.
+    ///
+    /// Bucket counters are still read, as their total is what tells in-flight observations
+    /// apart, but they are neither stored nor converted.
+    #[must_use = "use `rotate` to swap shards without reading them"]
+    pub fn collect_totals(&self) -> (u64, f64) {
+        let mut state = self.lock_collector();
+        // paced collections are cached in full, to be returned by every variant
+        if self.0.options.min_collect_interval.is_some() {
+            let raw = self.collect_raw_paced(&mut state);
+            return (raw.count, raw.sum);
+        }
+        let (bucket_count, spin_limit, waker) = (
+            self.0.bucket_count,
+            self.0.options.spin_limit,
+            &self.0.waker,
+        );
+        let read = |shard: usize| {
+            Some(self.0.shards[shard].collect_totals(bucket_count, spin_limit, waker))
+        };
+        let ((count_cold, sum_cold), (count_hot, sum_hot), _) =
+            (self.swap_and_read(read)).expect("read without deadline cannot time out");
+        let sum = Shard::<B>::shards_sum_into_f64(sum_cold, sum_hot);
+        (count_cold.saturating_add(count_hot), sum)
+    }
+
+    /// Same as [`collect`](Self::collect), but returns buckets in an array, without
+    /// heap allocation, for buckets whose count is known at compile time.
+    ///
+    /// `M` must be the count of bucket values plus one for the `+Inf` bucket,
+    /// which is checked at compile time.
+    #[must_use = "use `rotate` to swap shards without reading them"]
+    pub fn collect_array<const M: usize>(&self) -> (u64, f64, [(f64, u64); M]) {
+        const {
+            assert!(
+                matches!(B::LEN, Some(len) if len + 1 == M),
+                "`M` must be the count of bucket values plus one"
+            );
+        }
+        let mut state = self.lock_collector();
+        let (count, sum, counts): (_, _, [u64; M]) =
+            if self.0.options.min_collect_interval.is_some() {
+                let raw = self.collect_raw_paced(&mut state);
+                (raw.count, raw.sum, array::from_fn(|i| raw.counts[i]))
+            } else {
+                let (bucket_count, spin_limit, waker) = (
+                    self.0.bucket_count,
+                    self.0.options.spin_limit,
+                    &self.0.waker,
+                );
+                let read = |shard: usize| {
+                    Some(self.0.shards[shard].collect_array::<M>(bucket_count, spin_limit, waker))
+                };
+                let ((count_cold, sum_cold, counts_cold), (count_hot, sum_hot, counts_hot), _) =
+                    (self.swap_and_read(read)).expect("read without deadline cannot time out");
+                (
+                    count_cold.saturating_add(count_hot),
+                    Shard::<B>::shards_sum_into_f64(sum_cold, sum_hot),
+                    array::from_fn(|i| counts_cold[i].saturating_add(counts_hot[i])),
+                )
+            };
+        let buckets = array::from_fn(|i| {
+            let boundary = self.0.boundaries.get(i).copied();
+            (boundary.unwrap_or(f64::INFINITY), counts[i])
+        });
+        (count, sum, buckets)
+    }
+
+    /// Same as [`collect`](Self::collect), but returns `None` instead of blocking
+    /// if another collection is in progress.
+    ///
+    /// Collections are serialized, as only one collector can wait for in-flight
+    /// observations, so concurrent exporters can use this method to skip a
+    /// collection instead of waiting for the other one to complete.
+    ///
+    /// Each collection is consistent on its own, and concurrent ones are ordered: a
+    /// collection never misses observations returned by a previous one.
+    #[must_use = "use `rotate` to swap shards without reading them"]
+    pub fn try_collect(&self) -> Option<(u64, f64, impl Iterator<Item = (f64, u64)>)> {
+        self.try_collect_checked().ok()
+    }
+
+    /// Same as [`try_collect`](Self::try_collect), but returns an error instead of `None`.
+    #[must_use = "use `rotate` to swap shards without reading them"]
+    pub fn try_collect_checked(
+        &self,
+    ) -> Result<(u64, f64, impl Iterator<Item = (f64, u64)>), CollectError> {
+        let mut state = match self.0.collector.try_lock() {
+            Ok(guard) => guard,
+            Err(TryLockError::WouldBlock) => return Err(CollectError::WouldBlock),
+            Err(TryLockError::Poisoned(err)) => err.into_inner(),
+        };
+        let (count, sum, buckets, _) = self.collect_locked(&mut state);
+        Ok((count, sum, buckets))
+    }
+
+    // The collector mutex serializes collections and only protects the paced collection
+    // cache, which is replaced at once, so a collector panicking while holding it cannot
+    // leave an inconsistent state: poisoning is ignored.
+    //
+    // A seqlock, letting collectors retry instead of blocking, doesn't fit here: a
+    // collection is itself a writer, as it swaps shards and then waits for in-flight
+    // observations through the single waker slot, so a second collector could only spin
+    // while the first one waits. Contention between exporters is instead avoided by
+    // skipping with `try_collect`, and by `min_collect_interval`, which reduces the
+    // critical section of paced collections to a clone of the cached result; the time
+    // spent waiting for observers is bounded by `collect_timeout`.
+    fn lock_collector(&self) -> MutexGuard<'_, CollectorState> {
+        self.0
+            .collector
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Same as [`collect`](Self::collect), but returns an error instead of waiting more
+    /// than `timeout` for in-flight observations, e.g. if an observing thread has been
+    /// descheduled in the middle of an observation.
+    ///
+    /// Shards may have been swapped on timeout, but no observation is lost, as they are
+    /// returned by the next collection. Waiting for a concurrent collection is not bounded
+    /// by `timeout`.
+    #[cfg(not(loom))]
+    #[must_use = "use `rotate` to swap shards without reading them"]
+    pub fn collect_timeout(
+        &self,
+        timeout: Duration,
+    ) -> Result<(u64, f64, impl Iterator<Item = (f64, u64)>), TimeoutError> {
+        let deadline = Instant::now().checked_add(timeout);
+        let mut state = self.lock_collector();
+        let raw = (self.collect_raw_paced_until(&mut state, deadline)).ok_or(TimeoutError)?;
+        Ok((raw.count, raw.sum, self.with_boundaries(raw.counts)))
+    }
+
+    fn collect_locked<'a>(
+        &'a self,
+        state: &mut CollectorState,
+    ) -> (
+        u64,
+        f64,
+        impl Iterator<Item = (f64, u64)> + use<'a, B, TRUSTED_BUCKETS>,
+        CollectStats,
+    ) {
+        let raw = self.collect_raw_paced(state);
+        (
+            raw.count,
+            raw.sum,
+            self.with_boundaries(raw.counts),
+            raw.stats,
+        )
+    }
+
+    // Returns the last collection, without swapping shards, if it is more recent than the
+    // minimum interval. Every collection variant goes through it when collections are paced,
+    // so that the cache is never older than a returned result.
+    fn collect_raw_paced(&self, state: &mut CollectorState) -> RawCollected {
+        (self.collect_raw_paced_until(state, None))
+            .expect("collection without deadline cannot time out")
+    }
+
+    fn collect_raw_paced_until(
+        &self,
+        state: &mut CollectorState,
+        deadline: Option<Instant>,
+    ) -> Option<RawCollected> {
+        let Some(min_interval) = self.0.options.min_collect_interval else {
+            return self.collect_raw_locked_until(false, deadline);
+        };
+        if let Some((instant, raw)) = &state.last_collect
+            && instant.elapsed() < min_interval
+        {
+            return Some(raw.clone());
+        }
+        let raw = self.collect_raw_locked_until(false, deadline)?;
+        state.last_collect = Some((Instant::now(), raw.clone()));
+        Some(raw)
+    }
+
+    fn with_boundaries(&self, counts: Vec<u64>) -> impl Iterator<Item = (f64, u64)> {
+        (self.0.boundaries.iter().copied())
             .chain([f64::INFINITY])
-            .zip(iter::zip(buckets_cold, buckets_hot))
-            .map(|(b, (cold, hot))| (b, cold + hot));
-        (count_cold + count_hot, sum_cold + sum_hot, buckets)
+            .zip(counts)
+    }
+
+    fn collect_raw_locked(&self, reset: bool) -> RawCollected {
+        self.collect_raw_locked_until(reset, None)
+            .expect("collection without deadline cannot time out")
+    }
+
+    // a deadline is only used without reset, as a cold shard reset before the timeout of the
+    // hot one would lose its observations
+    fn collect_raw_locked_until(
+        &self,
+        reset: bool,
+        deadline: Option<Instant>,
+    ) -> Option<RawCollected> {
+        let read = |shard| Some((shard, self.read_shard_locked(shard, reset, deadline)?));
+        let (cold, hot, generation) = self.swap_and_read(read)?;
+        let (cold_shard, (count_cold, sum_cold, buckets_cold, diag_cold)) = cold;
+        let (hot_shard, (count_hot, sum_hot, buckets_hot, diag_hot)) = hot;
+        let mut diags = [ShardDiag::default(); 2];
+        diags[cold_shard] = diag_cold;
+        diags[hot_shard] = diag_hot;
+        let counts = sum_counts(buckets_cold, buckets_hot);
+        let stats = CollectStats {
+            generation,
+            cold_count: count_cold,
+            hot_count: count_hot,
+        };
+        Some(RawCollected {
+            count: count_cold.saturating_add(count_hot),
+            sum: Shard::<B>::shards_sum_into_f64(sum_cold, sum_hot),
+            raw_sums: [sum_cold, sum_hot],
+            counts,
+            stats,
+            diags,
+        })
+    }
+
+    // Reads the cold shard, swaps shards, and reads the previously hot shard, which may have
+    // to wait for in-flight observations. `read` is called with the shard index, and both
+    // reads are returned, the cold one first, with the generation of the swap.
+    fn swap_and_read<T>(&self, mut read: impl FnMut(usize) -> Option<T>) -> Option<(T, T, u64)> {
+        let hot_shard = self.0.hot_shard.load(Ordering::Relaxed);
+        let cold = read(hot_shard ^ 1)?;
+        let (_, generation) = self.swap_shards();
+        let hot = read(hot_shard)?;
+        Some((cold, hot, generation))
+    }
+
+    // the collector must be locked; returns the new hot shard and the new generation
+    fn swap_shards(&self) -> (usize, u64) {
+        let hot_shard = self.0.hot_shard.load(Ordering::Relaxed) ^ 1;
+        self.0.hot_shard.store(hot_shard, Ordering::Relaxed);
+        let generation = self.0.generation.fetch_add(1, Ordering::Relaxed) + 1;
+        (hot_shard, generation)
+    }
+
+    /// Returns an independent histogram with the same buckets and the current data,
+    /// contrary to [`Clone`] which shares the data.
+    ///
+    /// The copy is allocated with the global allocator.
+    #[must_use]
+    pub fn deep_copy(&self) -> Self
+    where
+        B: Clone,
+    {
+        let RawCollected {
+            count,
+            raw_sums,
+            counts,
+            ..
+        } = {
+            let _guard = self.lock_collector();
+            self.collect_raw_locked(false)
+        };
+        let copy = Histogram::with_options(self.0.buckets.clone(), self.0.options);
+        let histogram = Self(copy.0);
+        // buckets are the same, so counts are imported as is, without migration
+        histogram.import_raw(&counts, raw_sums, count);
+        histogram
+    }
+
+    /// Returns a new histogram with `new_buckets`, with the current data migrated.
+    ///
+    /// Each bucket count is moved to the first new bucket whose boundary is greater than
+    /// or equal to the old one, so migration is exact when new boundaries are a subset
+    /// of the old ones. Count and sum are preserved.
+    ///
+    /// Observations made on this histogram after the call are not migrated.
+    #[must_use]
+    pub fn reconfigure(&self, new_buckets: B) -> Self {
+        let RawCollected {
+            count,
+            raw_sums,
+            counts,
+            ..
+        } = {
+            let _guard = self.lock_collector();
+            self.collect_raw_locked(false)
+        };
+        let histogram = Self(Histogram::with_options(new_buckets, self.0.options).0);
+        let new_counts = self.migrate_counts(&counts, &histogram.0.boundaries);
+        histogram.import_raw(&new_counts, raw_sums, count);
+        histogram
+    }
+
+    /// Same as [`reconfigure`](Self::reconfigure), but replaces the buckets of this
+    /// histogram, whose counters are reallocated, e.g. to release memory after reducing
+    /// the bucket count. Counters allocated with [`new_in`](Self::new_in) keep their allocator.
+    ///
+    /// Returns `new_buckets` back if the histogram has clones, as they could observe
+    /// concurrently with the reallocation.
+    ///
+    /// # Panics
+    ///
+    /// There must be at most [`max_buckets`](HistogramBuilder::max_buckets) bucket values.
+    pub fn reconfigure_in_place(&mut self, new_buckets: B) -> Result<(), B> {
+        if Arc::get_mut(&mut self.0).is_none() {
+            return Err(new_buckets);
+        }
+        let len = Self::len(&new_buckets);
+        if len > self.0.options.max_buckets {
+            let max = self.0.options.max_buckets;
+            panic!("{}: {len} > {max}", HistogramError::TooManyBuckets);
+        }
+        let bucket_count = Self::BUCKET_COUNT.unwrap_or_else(|| Self::bucket_count(len));
+        let boundaries: Box<[f64]> = new_buckets.values().map(B::Value::into_f64).collect();
+        let RawCollected {
+            count,
+            raw_sums,
+            counts,
+            ..
+        } = {
+            let mut state = self.lock_collector();
+            state.last_collect = None;
+            self.collect_raw_locked(false)
+        };
+        let new_counts = self.migrate_counts(&counts, &boundaries);
+        let inner = Arc::get_mut(&mut self.0).expect("histogram has no clone");
+        for shard in &mut inner.shards {
+            shard.reallocate(inner.bucket_count, bucket_count);
+        }
+        inner.buckets = new_buckets;
+        inner.boundaries = boundaries;
+        inner.bucket_count = bucket_count;
+        self.import_raw(&new_counts, raw_sums, count);
+        Ok(())
+    }
+
+    // each count is moved to the first new bucket whose boundary is greater or equal
+    fn migrate_counts(&self, counts: &[u64], new_boundaries: &[f64]) -> Vec<u64> {
+        let new_bucket_count = Self::bucket_count(new_boundaries.len());
+        let mut new_counts = vec![0; new_bucket_count];
+        let old_boundaries = self.0.boundaries.iter().chain([&f64::INFINITY]);
+        for (boundary, bucket_count) in iter::zip(old_boundaries, counts) {
+            let index = new_boundaries.partition_point(|b| b < boundary);
+            new_counts[index] += bucket_count;
+        }
+        if B::Value::HAS_NAN {
+            *new_counts.last_mut().unwrap() += counts.last().unwrap();
+        }
+        new_counts
+    }
+
+    /// Adds pre-aggregated data to the histogram, e.g. [`collect`](Self::collect)
+    /// output of another histogram.
+    ///
+    /// `counts` are bucket counts, including `+Inf` bucket, like returned by
+    /// [`collect_counts`](Self::collect_counts), and `sum` is expressed in
+    /// [`HistogramValue::into_f64`] unit. As in `collect` output, `NaN` observations are
+    /// only in `count`, so they are its difference with the sum of `counts`.
+    ///
+    /// # Panics
+    ///
+    /// `counts` length must be the count of bucket values plus one, and `count` must be
+    /// the sum of `counts`, or greater for values with `NaN`.
+    pub fn import(&self, counts: &[u64], sum: f64, count: u64) {
+        assert_eq!(
+            counts.len(),
+            self.0.boundaries.len() + 1,
+            "invalid bucket count"
+        );
+        let nan_count = counts
+            .iter()
+            .try_fold(0u64, |acc, c| acc.checked_add(*c))
+            .and_then(|total| count.checked_sub(total));
+        assert!(
+            nan_count.is_some_and(|nan_count| nan_count == 0 || B::Value::HAS_NAN),
+            "invalid count"
+        );
+        let mut raw_counts = counts.to_vec();
+        if B::Value::HAS_NAN {
+            raw_counts.extend(nan_count);
+        }
+        let sum = if self.0.options.track_sum { sum } else { 0.0 };
+        let hot_shard = self.0.hot_shard.load(Ordering::Relaxed);
+        self.0.shards[hot_shard].import(&raw_counts, sum, count, &self.0.waker);
+    }
+
+    // same as `import`, but with raw shard sums, so that sums are not rounded to `f64`
+    fn import_raw(&self, counts: &[u64], sums: [u128; 2], count: u64) {
+        let hot_shard = self.0.hot_shard.load(Ordering::Relaxed);
+        self.0.shards[hot_shard].import_raw(counts, &sums, count, &self.0.waker);
+    }
+
+    /// Returns the count of the `+Inf` bucket, i.e. observations above every bucket value,
+    /// which usually signals misconfigured buckets.
+    ///
+    /// Contrary to [`collect`](Self::collect), shards are neither swapped nor waited for, so
+    /// the result may miss in-flight observations.
+    pub fn overflow_count(&self) -> u64 {
+        let inf_bucket = self.0.bucket_count - 1 - B::Value::HAS_NAN as usize;
+        (self.0.shards.iter())
+            .map(|shard| shard.counters.bucket(inf_bucket).load(Ordering::Relaxed))
+            .fold(0, u64::saturating_add)
+    }
+
+    /// Returns the memory used by the counters of both shards, including cache padding.
+    pub fn capacity_bytes(&self) -> usize {
+        let size = <impls::Counters as HistogramCounters>::size(self.0.bucket_count);
+        2 * size.expect("counters have been allocated")
+    }
+
+    /// Adds zero to every counter of both shards, so their pages are faulted in and
+    /// their cache lines acquired before a latency-sensitive workload starts, instead
+    /// of on the first observations.
+    ///
+    /// Lines may still be evicted or acquired by other cores before being observed in.
+    pub fn prewarm(&self) {
+        for shard in &self.0.shards {
+            shard.prewarm(self.0.bucket_count);
+        }
+    }
+
+    /// Returns the number of times an observation woke up a collector waiting for it.
+    #[cfg(any(test, feature = "testutil"))]
+    pub fn wake_count(&self) -> u64 {
+        self.0.waker.wakes.load(Ordering::Relaxed)
+    }
+
+    /// Swaps shards without reading them, and returns the new hot shard.
+    ///
+    /// Collections already swap shards, so it is only useful when the swap itself is
+    /// intended, e.g. to control which shard receives observations.
+    pub fn rotate(&self) -> ShardId {
+        let _guard = self.lock_collector();
+        ShardId::from_index(self.swap_shards().0)
+    }
+
+    pub fn hot_shard(&self) -> ShardId {
+        ShardId::from_index(self.0.hot_shard.load(Ordering::Relaxed))
+    }
+
+    /// Returns the current count of each shard, indexed by [`ShardId`], to check that
+    /// observations are balanced between shards.
+    ///
+    /// Counts are loaded without waiting for in-flight observations.
+    pub fn shard_counts(&self) -> [u64; 2] {
+        let _guard = self.lock_collector();
+        (self.0.shards.each_ref())
+            .map(|shard| shard.counters.count().load(Ordering::Relaxed) & !Shard::<B>::WAITING_FLAG)
+    }
+
+    /// Returns whether no observation has been counted since creation or last reset,
+    /// e.g. to skip exporting an empty histogram before collecting it.
+    ///
+    /// Neither the collector is locked nor shards swapped, so in-flight observations
+    /// may not be seen yet.
+    pub fn is_empty(&self) -> bool {
+        (self.0.shards.iter()).all(|shard| {
+            shard.counters.count().load(Ordering::Relaxed) & !Shard::<B>::WAITING_FLAG == 0
+        })
+    }
+
+    /// Reads a single shard, without swapping shards.
+    ///
+    /// The result is only consistent for the read shard, and is not a complete
+    /// view of the histogram, which is split across both shards. Reading the hot
+    /// shard may also have to wait for in-flight observations, and a concurrent
+    /// [`collect`](Self::collect) may swap shards between two reads.
+    ///
+    /// Raw bucket counts are returned, including `+Inf` and `NaN` (if any) buckets.
+    #[must_use]
+    pub fn read_shard(&self, which: ShardId) -> (u64, f64, Vec<u64>) {
+        let _guard = self.lock_collector();
+        let (count, sum, buckets, _) = (self.read_shard_locked(which as usize, false, None))
+            .expect("read without deadline cannot time out");
+        (count, Shard::<B>::sum_into_f64(sum), buckets)
+    }
+
+    /// Same as [`read_shard`](Self::read_shard), for the shard not receiving observations.
+    ///
+    /// Combined with [`rotate`](Self::rotate), it decomposes a collection: reading the cold
+    /// shard, rotating, and reading the new cold shard returns the data of
+    /// [`collect`](Self::collect), split between both shards.
+    #[must_use]
+    pub fn read_cold(&self) -> (u64, f64, Vec<u64>) {
+        let _guard = self.lock_collector();
+        let cold_shard = self.0.hot_shard.load(Ordering::Relaxed) ^ 1;
+        let (count, sum, buckets, _) = (self.read_shard_locked(cold_shard, false, None))
+            .expect("read without deadline cannot time out");
+        (count, Shard::<B>::sum_into_f64(sum), buckets)
+    }
+
+    // observations are subtracted from the shard on reset, as zeroing counters
+    // would lose concurrent increments
+    fn read_shard_locked(
+        &self,
+        shard: usize,
+        reset: bool,
+        deadline: Option<Instant>,
+    ) -> Option<(u64, u128, Vec<u64>, ShardDiag)> {
+        let shard = &self.0.shards[shard];
+        let (count, sum, buckets, diag) = shard.collect(
+            self.0.bucket_count,
+            self.0.options.spin_limit,
+            &self.0.waker,
+            deadline,
+        )?;
+        if reset {
+            shard.subtract(&buckets, sum, count);
+        }
+        Some((count, sum, buckets, diag))
+    }
+
+    /// Same as [`collect`](Self::collect), but also resets the histogram, so that
+    /// next collections only return later observations.
+    ///
+    /// Like collections, resets wait for in-flight observations, and only subtract what
+    /// has been collected: a concurrent observation is either fully returned and reset,
+    /// or fully kept for the next collection.
+    ///
+    /// Each reset increments the [`reset_epoch`](Self::reset_epoch).
+    #[must_use = "use `reset` to discard the collected data"]
+    pub fn collect_and_reset(&self) -> (u64, f64, impl Iterator<Item = (f64, u64)>) {
+        let mut state = self.lock_collector();
+        // a paced collection would return data from before the reset
+        state.last_collect = None;
+        let raw = self.collect_raw_locked(true);
+        self.0.reset_epoch.fetch_add(1, Ordering::Relaxed);
+        (raw.count, raw.sum, self.with_boundaries(raw.counts))
+    }
+
+    pub fn reset(&self) {
+        let _ = self.collect_and_reset();
+    }
+
+    /// Returns the number of resets, so that exporters can detect them,
+    /// e.g. to bump the `_created` timestamp.
+    pub fn reset_epoch(&self) -> u64 {
+        self.0.reset_epoch.load(Ordering::Relaxed)
     }
 }
 
@@ -120,14 +1231,158 @@ impl<B: HistogramBuckets, const TRUSTED_BUCKET: bool> Clone for Histogram<B, TRU
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShardId {
+    First,
+    Second,
+}
+
+impl ShardId {
+    fn from_index(index: usize) -> Self {
+        match index {
+            0 => Self::First,
+            _ => Self::Second,
+        }
+    }
+}
+
+/// Shard accounting of a [`Histogram::collect_with_stats`].
+///
+/// Every observation is counted in exactly one shard, so none is ever lost
+/// nor double-counted across collects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CollectStats {
+    /// Number of shard swaps of the histogram, including the one of this collect.
+    ///
+    /// It is histogram-wide, counting swaps of [`rotate`](Histogram::rotate) too,
+    /// so it orders collects but tells nothing about either shard; a paced collect
+    /// returns the generation of the cached one.
+    pub generation: u64,
+    /// Observations of the shard that was cold before the swap.
+    pub cold_count: u64,
+    /// Observations of the shard that was hot before the swap, including
+    /// the ones racing with the swap.
+    pub hot_count: u64,
+}
+
+const SPIN_LOOP_LIMIT: usize = if cfg!(not(loom)) { 10 } else { 1 };
+
+// Below this bucket count, parallelism overhead exceeds the gain of reading counters
+// and summing shards in parallel.
+#[cfg(all(feature = "rayon", not(loom)))]
+const PARALLEL_BUCKET_COUNT: usize = 1 << 14;
+
+// counts saturate, as each shard can reach `u64::MAX` with a separate waiting flag
+fn sum_counts(cold: Vec<u64>, hot: Vec<u64>) -> Vec<u64> {
+    #[cfg(all(feature = "rayon", not(loom)))]
+    if cold.len() >= PARALLEL_BUCKET_COUNT {
+        use rayon::prelude::*;
+        return (cold.into_par_iter().zip(hot))
+            .map(|(cold, hot)| cold.saturating_add(hot))
+            .collect();
+    }
+    (iter::zip(cold, hot))
+        .map(|(cold, hot)| cold.saturating_add(hot))
+        .collect()
+}
+
+#[derive(Debug, Default)]
+struct CollectorState {
+    last_collect: Option<(Instant, RawCollected)>,
+}
+
+// raw bucket counts include `+Inf` and `NaN` (if any) buckets
+#[derive(Debug, Clone)]
+struct RawCollected {
+    count: u64,
+    sum: f64,
+    // sums of the cold and hot shards, in their raw bits representation
+    raw_sums: [u128; 2],
+    counts: Vec<u64>,
+    stats: CollectStats,
+    diags: [ShardDiag; 2],
+}
+
+/// Diagnostics of the collection of a shard, returned by [`Histogram::collect_with_diag`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ShardDiag {
+    /// Number of reads of the shard before the cold path, including the successful one.
+    pub spins: usize,
+    /// Whether the collector had to wait for in-flight observations.
+    pub cold: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CollectError {
+    /// Another collection is in progress.
+    WouldBlock,
+}
+
+impl fmt::Display for CollectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WouldBlock => write!(f, "another collection is in progress"),
+        }
+    }
+}
+
+impl error::Error for CollectError {}
+
+/// Error returned by [`Histogram::collect_timeout`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeoutError;
+
+impl fmt::Display for TimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "timed out waiting for in-flight observations")
+    }
+}
+
+impl error::Error for TimeoutError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistogramError {
+    /// Counters size exceeds `isize::MAX` bytes.
+    CapacityOverflow,
+    /// Bucket values are not strictly increasing.
+    UnsortedBuckets,
+    /// A bucket value is `NaN`.
+    NanBoundary,
+    /// There is no bucket value.
+    EmptyBuckets,
+    /// There are more bucket values than the maximum, [`MAX_BUCKETS`] by default.
+    TooManyBuckets,
+}
+
+impl fmt::Display for HistogramError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CapacityOverflow => write!(f, "capacity overflow"),
+            Self::UnsortedBuckets => write!(f, "bucket values are not strictly increasing"),
+            Self::NanBoundary => write!(f, "bucket value is NaN"),
+            Self::EmptyBuckets => write!(f, "empty buckets"),
+            Self::TooManyBuckets => write!(f, "too many buckets"),
+        }
+    }
+}
+
+impl error::Error for HistogramError {}
+
 #[derive(Debug)]
 struct HistogramInner<B: HistogramBuckets> {
     buckets: B,
+    // bucket values converted once, so collections don't recompute them
+    boundaries: Box<[f64]>,
     bucket_count: usize,
     hot_shard: AtomicUsize,
     shards: [Shard<B>; 2],
-    collector: Mutex<()>,
-    waker: AtomicWaker,
+    generation: AtomicU64,
+    reset_epoch: AtomicU64,
+    collector: Mutex<CollectorState>,
+    waker: CollectorWaker,
+    options: Options,
+    #[cfg(feature = "publish")]
+    published: arc_swap::ArcSwapOption<HistogramSnapshot>,
 }
 
 #[cfg(feature = "unsafe")]
@@ -141,51 +1396,254 @@ impl<B: HistogramBuckets> Drop for HistogramInner<B> {
 
 trait HistogramCounters {
     fn new(bucket_count: usize) -> Self;
-    fn count(&self) -> &AtomicU64;
-    fn sum(&self) -> &AtomicU64;
-    fn bucket(&self, bucket_index: usize) -> &AtomicU64;
-    fn buckets(&self, bucket_count: usize) -> impl Iterator<Item = &AtomicU64>;
+    // memory used by the counters, including padding
+    fn size(bucket_count: usize) -> Option<usize>;
+    fn count(&self) -> &AtomicCounter;
+    fn sum(&self) -> &AtomicCounter;
+    fn bucket(&self, bucket_index: usize) -> &AtomicCounter;
+    fn buckets(&self, bucket_count: usize) -> impl Iterator<Item = &AtomicCounter>;
     #[cfg(feature = "unsafe")]
     fn drop(&mut self, bucket_count: usize) {
         let _ = bucket_count;
     }
+    // replaces the counters with zeroed ones, freeing the old ones
+    fn reallocate(&mut self, old_bucket_count: usize, bucket_count: usize)
+    where
+        Self: Sized,
+    {
+        let _ = old_bucket_count;
+        *self = Self::new(bucket_count);
+    }
+}
+
+#[derive(Debug)]
+struct CollectorWaker {
+    waker: AtomicWaker,
+    #[cfg(any(test, feature = "testutil"))]
+    wakes: AtomicU64,
+}
+
+impl CollectorWaker {
+    fn new() -> Self {
+        Self {
+            waker: AtomicWaker::new(),
+            #[cfg(any(test, feature = "testutil"))]
+            wakes: AtomicU64::new(0),
+        }
+    }
+
+    fn wake_collector(&self) {
+        #[cfg(any(test, feature = "testutil"))]
+        self.wakes.fetch_add(1, Ordering::Relaxed);
+        self.waker.wake();
+    }
+}
+
+impl Deref for CollectorWaker {
+    type Target = AtomicWaker;
+
+    fn deref(&self) -> &Self::Target {
+        &self.waker
+    }
 }
 
 #[derive(Debug)]
 struct Shard<B> {
     counters: impls::Counters,
+    // only used by values with a wide sum, and only written on carry for most observations
+    sum_high: AtomicCounter,
+    #[cfg(feature = "separate-waiting-flag")]
+    waiting: AtomicBool,
     _phantom: PhantomData<B>,
 }
 
 impl<B: HistogramBuckets> Shard<B> {
-    const SPIN_LOOP_LIMIT: usize = if cfg!(not(loom)) { 10 } else { 1 };
+    // The flag shares the count atomic, so a shard is assumed to count less than 2^63
+    // observations, which is not checked: more would carry into the flag, and be masked
+    // out of the count. Summing both shards in `collect` saturates anyway.
+    #[cfg(not(feature = "separate-waiting-flag"))]
     const WAITING_FLAG: u64 = 1 << (u64::BITS - 1);
+    // The flag has its own atomic, so masking the count is a no-op, and the count is only
+    // capped by `u64`; summing both shards in `collect` then saturates.
+    // The collector sets the flag before a no-op RMW on the count: either the observer
+    // increment is ordered after it, and synchronizes with it to see the flag, or before
+    // it, and the collector sees the increment.
+    #[cfg(feature = "separate-waiting-flag")]
+    const WAITING_FLAG: u64 = 0;
+    const COUNT_ORDERING: Ordering = match cfg!(feature = "separate-waiting-flag") {
+        true => Ordering::AcqRel,
+        false => Ordering::Release,
+    };
 
-    fn new(bucket_count: usize) -> Self {
+    fn new(counters: impls::Counters) -> Self {
         Self {
-            counters: HistogramCounters::new(bucket_count),
+            counters,
+            sum_high: AtomicCounter::new(0),
+            #[cfg(feature = "separate-waiting-flag")]
+            waiting: AtomicBool::new(false),
             _phantom: PhantomData,
         }
     }
 
-    fn observe(&self, value: B::Value, bucket_index: usize, waker: &AtomicWaker) {
+    fn observe(
+        &self,
+        value: &B::Value,
+        bucket_index: usize,
+        track_sum: bool,
+        waker: &CollectorWaker,
+    ) {
         self.counters
             .bucket(bucket_index)
             .fetch_add(1, Ordering::Relaxed);
-        B::Value::atomic_add(self.counters.sum(), value, Ordering::Release);
-        let count = self.counters.count().fetch_add(1, Ordering::Release);
-        if count & Self::WAITING_FLAG != 0 {
+        // `Release` cannot be relaxed: a collector reading a sum including this observation
+        // must also see the bucket increment, otherwise a torn sum would pass the count check
+        match (track_sum, B::Value::WIDE_SUM) {
+            (false, _) => {}
+            (true, true) => {
+                let (sum, sum_high) = (self.counters.sum(), &self.sum_high);
+                B::Value::atomic_add_wide(sum, sum_high, value, Ordering::Release);
+            }
+            (true, false) => {
+                B::Value::atomic_add_ref(self.counters.sum(), value, Ordering::Release);
+            }
+        }
+        let count = self.counters.count().fetch_add(1, Self::COUNT_ORDERING);
+        if self.is_waiting(count) {
             #[cold]
-            fn wake(waker: &AtomicWaker) {
-                waker.wake();
+            fn wake(waker: &CollectorWaker) {
+                waker.wake_collector();
             }
             wake(waker);
         }
     }
 
-    fn read_sum_and_buckets(&self, buckets: &mut [u64]) -> (f64, u64) {
+    fn import(&self, counts: &[u64], sum: f64, count: u64, waker: &CollectorWaker) {
+        self.add_to_buckets(counts);
+        self.add_sum_and_count(sum, count, waker);
+    }
+
+    // `sums` are in their raw bits representation, like returned by `load_sum`
+    fn import_raw(&self, counts: &[u64], sums: &[u128], count: u64, waker: &CollectorWaker) {
+        self.add_to_buckets(counts);
+        for sum in sums {
+            self.add_sum_bits(*sum);
+        }
+        self.add_count(count, waker);
+    }
+
+    fn add_to_buckets(&self, counts: &[u64]) {
+        for (bucket, counter) in counts.iter().zip(self.counters.buckets(counts.len())) {
+            counter.fetch_add(*bucket, Ordering::Relaxed);
+        }
+    }
+
+    // `count` is the one returned by the increment
+    fn is_waiting(&self, count: u64) -> bool {
+        #[cfg(feature = "separate-waiting-flag")]
+        {
+            let _ = count;
+            self.waiting.load(Ordering::Relaxed)
+        }
+        #[cfg(not(feature = "separate-waiting-flag"))]
+        {
+            count & Self::WAITING_FLAG != 0
+        }
+    }
+
+    // returns the count, read after the flag is set
+    fn set_waiting_flag(&self) -> u64 {
+        #[cfg(feature = "separate-waiting-flag")]
+        {
+            self.waiting.store(true, Ordering::Relaxed);
+            self.counters.count().fetch_add(0, Ordering::AcqRel)
+        }
+        #[cfg(not(feature = "separate-waiting-flag"))]
+        {
+            (self.counters.count()).fetch_or(Self::WAITING_FLAG, Ordering::Acquire)
+                & !Self::WAITING_FLAG
+        }
+    }
+
+    // batched observations must be completed with `add_count`, after their sum is added
+    fn add_to_bucket(&self, bucket_index: usize, weight: u64) {
+        (self.counters.bucket(bucket_index)).fetch_add(weight, Ordering::Relaxed);
+    }
+
+    fn add_sum_and_count(&self, sum: f64, count: u64, waker: &CollectorWaker) {
+        if B::Value::WIDE_SUM {
+            let (low, high) = (self.counters.sum(), &self.sum_high);
+            B::Value::atomic_add_wide(low, high, &B::Value::from_f64(sum), Ordering::Release);
+        } else {
+            B::Value::atomic_add_sum(self.counters.sum(), sum, Ordering::Release);
+        }
+        self.add_count(count, waker);
+    }
+
+    // `sum` is in its raw bits representation, like returned by `load_sum`
+    fn add_sum_bits(&self, sum: u128) {
+        if B::Value::WIDE_SUM {
+            let (low, high) = (sum as u64, (sum >> u64::BITS) as u64);
+            let previous = self.counters.sum().fetch_add(low, Ordering::Release);
+            let carry = previous.overflowing_add(low).1;
+            (self.sum_high).fetch_add(high.wrapping_add(carry.into()), Ordering::Release);
+        } else {
+            B::Value::atomic_add_bits(self.counters.sum(), sum as u64, Ordering::Release);
+        }
+    }
+
+    fn add_count(&self, count: u64, waker: &CollectorWaker) {
+        if self.is_waiting(self.counters.count().fetch_add(count, Self::COUNT_ORDERING)) {
+            waker.wake_collector();
+        }
+    }
+
+    fn prewarm(&self, bucket_count: usize) {
+        // a load would not fault in zero pages mapped copy-on-write, nor acquire lines
+        // exclusively, so counters are written with a no-op addition instead
+        let counters = [self.counters.count(), self.counters.sum(), &self.sum_high];
+        for counter in counters
+            .into_iter()
+            .chain(self.counters.buckets(bucket_count))
+        {
+            counter.fetch_add(0, Ordering::Relaxed);
+        }
+    }
+
+    // the sum is returned in its raw bits representation, with the high counter in upper bits;
+    // both counters are read before the buckets, so a partially added wide sum is detected
+    // like any other in-flight observation
+    fn load_sum(&self) -> u128 {
+        let low = self.counters.sum().load(Ordering::Acquire);
+        let high = match B::Value::WIDE_SUM {
+            true => self.sum_high.load(Ordering::Acquire),
+            false => 0,
+        };
+        (u128::from(high) << u64::BITS) | u128::from(low)
+    }
+
+    fn sum_into_f64(sum: u128) -> f64 {
+        B::Value::wide_sum_from_bits(sum as u64, (sum >> u64::BITS) as u64)
+    }
+
+    fn shards_sum_into_f64(cold: u128, hot: u128) -> f64 {
+        Self::sum_into_f64(cold) + Self::sum_into_f64(hot)
+    }
+
+    fn read_sum_and_buckets(&self, buckets: &mut [u64]) -> (u128, u64) {
         let bucket_count = buckets.len();
-        let sum = B::Value::from_bits(self.counters.sum().load(Ordering::Acquire)).into_f64();
+        let sum = self.load_sum();
+        #[cfg(all(feature = "rayon", not(loom)))]
+        if bucket_count >= PARALLEL_BUCKET_COUNT {
+            use rayon::prelude::*;
+            let counters = &self.counters;
+            let expected_count = (buckets.par_iter_mut().enumerate())
+                .map(|(bucket_index, count)| {
+                    *count = counters.bucket(bucket_index).load(Ordering::Relaxed);
+                    *count
+                })
+                .sum();
+            return (sum, expected_count);
+        }
         let mut expected_count = 0;
         for (count, counter) in buckets.iter_mut().zip(self.counters.buckets(bucket_count)) {
             *count = counter.load(Ordering::Relaxed);
@@ -194,48 +1652,195 @@ impl<B: HistogramBuckets> Shard<B> {
         (sum, expected_count)
     }
 
-    fn collect(&self, bucket_count: usize, waker: &AtomicWaker) -> (u64, f64, Vec<u64>) {
+    // buckets are only summed, without being stored
+    fn read_sum_and_total(&self, bucket_count: usize) -> (u128, u64) {
+        let sum = self.load_sum();
+        let buckets = self.counters.buckets(bucket_count);
+        (
+            sum,
+            buckets.map(|counter| counter.load(Ordering::Relaxed)).sum(),
+        )
+    }
+
+    // the sum is returned in its raw bits representation, and `None` only on deadline expiry
+    fn collect(
+        &self,
+        bucket_count: usize,
+        spin_limit: usize,
+        waker: &CollectorWaker,
+        deadline: Option<Instant>,
+    ) -> Option<(u64, u128, Vec<u64>, ShardDiag)> {
         let mut buckets = vec![0; bucket_count];
-        for _ in 0..Self::SPIN_LOOP_LIMIT {
+        let read = || self.read_sum_and_buckets(&mut buckets);
+        let (count, sum, diag) = self.wait_consistent(read, spin_limit, waker, deadline)?;
+        Some((count, sum, buckets, diag))
+    }
+
+    fn collect_totals(
+        &self,
+        bucket_count: usize,
+        spin_limit: usize,
+        waker: &CollectorWaker,
+    ) -> (u64, u128) {
+        let read = || self.read_sum_and_total(bucket_count);
+        let (count, sum, _) = (self.wait_consistent(read, spin_limit, waker, None))
+            .expect("read without deadline cannot time out");
+        (count, sum)
+    }
+
+    // only the first `M` bucket counts are stored, the remaining ones, i.e. the `NaN` bucket,
+    // being only totaled
+    fn collect_array<const M: usize>(
+        &self,
+        bucket_count: usize,
+        spin_limit: usize,
+        waker: &CollectorWaker,
+    ) -> (u64, u128, [u64; M]) {
+        let mut buckets = [0; M];
+        let read = || {
+            let (sum, stored) = self.read_sum_and_buckets(&mut buckets);
+            let remaining = self.counters.buckets(bucket_count).skip(M);
+            (
+                sum,
+                stored + remaining.map(|c| c.load(Ordering::Relaxed)).sum::<u64>(),
+            )
+        };
+        let (count, sum, _) = (self.wait_consistent(read, spin_limit, waker, None))
+            .expect("read without deadline cannot time out");
+        (count, sum, buckets)
+    }
+
+    // `read` returns the sum and the total of bucket counts, which must match the count
+    // for the read to be consistent
+    fn wait_consistent(
+        &self,
+        mut read: impl FnMut() -> (u128, u64),
+        spin_limit: usize,
+        waker: &CollectorWaker,
+        deadline: Option<Instant>,
+    ) -> Option<(u64, u128, ShardDiag)> {
+        for spins in 1..=spin_limit {
+            if spins > 1 {
+                hint::spin_loop();
+            }
             let count = self.counters.count().load(Ordering::Acquire) & !Self::WAITING_FLAG;
-            let (sum, expected_count) = self.read_sum_and_buckets(&mut buckets);
+            let (sum, expected_count) = read();
             if count == expected_count {
-                return (count, sum, buckets);
+                let diag = ShardDiag { spins, cold: false };
+                return Some((count, sum, diag));
             }
         }
-        self.collect_cold(&mut buckets, waker)
+        let (count, sum) = self.collect_cold(&mut read, waker, deadline)?;
+        let diag = ShardDiag {
+            spins: spin_limit,
+            cold: true,
+        };
+        Some((count, sum, diag))
     }
 
     #[cold]
-    fn collect_cold(&self, buckets: &mut Vec<u64>, waker: &AtomicWaker) -> (u64, f64, Vec<u64>) {
-        block_on(poll_fn(move |cx| {
+    fn collect_cold(
+        &self,
+        read: &mut impl FnMut() -> (u128, u64),
+        waker: &CollectorWaker,
+        deadline: Option<Instant>,
+    ) -> Option<(u64, u128)> {
+        let poll = |cx: &mut Context| self.poll_collect(cx, read, waker);
+        match deadline {
             #[cfg(not(loom))]
-            waker.register(cx.waker());
+            Some(deadline) => Self::block_on_until(poll, deadline, || {
+                self.clear_waiting_flag(waker);
+            }),
+            _ => Some(block_on(poll_fn(poll))),
+        }
+    }
+
+    fn poll_collect(
+        &self,
+        cx: &mut Context,
+        read: &mut impl FnMut() -> (u128, u64),
+        waker: &CollectorWaker,
+    ) -> Poll<(u64, u128)> {
+        #[cfg(not(loom))]
+        waker.register(cx.waker());
+        #[cfg(loom)]
+        waker.register(cx.waker().clone());
+        let count = self.set_waiting_flag();
+        let (sum, expected_count) = read();
+        if count == expected_count {
+            self.clear_waiting_flag(waker);
+            return Poll::Ready((count, sum));
+        }
+        Poll::Pending
+    }
+
+    fn clear_waiting_flag(&self, waker: &CollectorWaker) {
+        #[cfg(feature = "separate-waiting-flag")]
+        let was_waiting = self.waiting.swap(false, Ordering::Relaxed);
+        #[cfg(not(feature = "separate-waiting-flag"))]
+        let was_waiting = (self.counters.count()).fetch_and(!Self::WAITING_FLAG, Ordering::Relaxed)
+            & Self::WAITING_FLAG
+            != 0;
+        if was_waiting {
+            #[cfg(not(loom))]
+            waker.take();
             #[cfg(loom)]
-            waker.register(cx.waker().clone());
-            let count = (self.counters.count()).fetch_or(Self::WAITING_FLAG, Ordering::Acquire)
-                & !Self::WAITING_FLAG;
-            let (sum, expected_count) = self.read_sum_and_buckets(buckets);
-            if count == expected_count {
-                if (self.counters.count()).fetch_and(!Self::WAITING_FLAG, Ordering::Relaxed)
-                    & Self::WAITING_FLAG
-                    != 0
-                {
-                    #[cfg(not(loom))]
-                    waker.take();
-                    #[cfg(loom)]
-                    waker.take_waker();
-                }
-                return Poll::Ready((count, sum, mem::take(buckets)));
+            waker.take_waker();
+        }
+    }
+
+    // `futures_executor::block_on` has no timeout, so the thread is parked with one instead
+    #[cfg(not(loom))]
+    fn block_on_until<T>(
+        mut poll: impl FnMut(&mut Context) -> Poll<T>,
+        deadline: Instant,
+        on_timeout: impl FnOnce(),
+    ) -> Option<T> {
+        struct ThreadWaker(thread::Thread);
+        impl Wake for ThreadWaker {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
             }
-            Poll::Pending
-        }))
+        }
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            if let Poll::Ready(res) = poll(&mut cx) {
+                return Some(res);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                on_timeout();
+                return None;
+            }
+            thread::park_timeout(deadline - now);
+        }
+    }
+
+    // only called by the collector, so the shard cannot be read concurrently
+    fn subtract(&self, counts: &[u64], sum: u128, count: u64) {
+        for (bucket, counter) in counts.iter().zip(self.counters.buckets(counts.len())) {
+            counter.fetch_sub(*bucket, Ordering::Relaxed);
+        }
+        if B::Value::WIDE_SUM {
+            let (low, high) = (sum as u64, (sum >> u64::BITS) as u64);
+            let borrow = self.counters.sum().fetch_sub(low, Ordering::Relaxed) < low;
+            (self.sum_high).fetch_sub(high.wrapping_add(borrow.into()), Ordering::Relaxed);
+        } else {
+            B::Value::atomic_sub_bits(self.counters.sum(), sum as u64, Ordering::Relaxed);
+        }
+        self.counters.count().fetch_sub(count, Ordering::Relaxed);
     }
 
     #[cfg(feature = "unsafe")]
     fn drop(&mut self, bucket_count: usize) {
         self.counters.drop(bucket_count);
     }
+
+    fn reallocate(&mut self, old_bucket_count: usize, bucket_count: usize) {
+        self.counters.reallocate(old_bucket_count, bucket_count);
+        self.sum_high.store(0, Ordering::Relaxed);
+    }
 }
 
 #[cfg(feature = "prometheus-client")]
@@ -250,8 +1855,18 @@ impl<B: HistogramBuckets, const TRUSTED_BUCKETS: bool> EncodeMetric
     for Histogram<B, TRUSTED_BUCKETS>
 {
     fn encode(&self, mut encoder: MetricEncoder) -> Result<(), Error> {
+        thread_local! {
+            // reused across scrapes, as the encoder requires a slice
+            static BUCKETS: Cell<Vec<(f64, u64)>> = const { Cell::new(Vec::new()) };
+        }
         let (count, sum, buckets) = self.collect();
-        encoder.encode_histogram::<NoLabelSet>(sum, count, &buckets.collect::<Vec<_>>(), None)
+        // taken rather than borrowed, so that nested encodings cannot conflict
+        let mut buf = BUCKETS.take();
+        buf.clear();
+        buf.extend(buckets);
+        let res = encoder.encode_histogram::<NoLabelSet>(sum, count, &buf, None);
+        BUCKETS.set(buf);
+        res
     }
 
     fn metric_type(&self) -> MetricType {
@@ -270,3 +1885,9 @@ pub fn observe_f64(h: &Histogram<&[f64], { cfg!(feature = "unsafe") }>, v: f64)
 pub fn observe_u64(h: &Histogram<&[u64], { cfg!(feature = "unsafe") }>, v: u64) {
     h.observe(v)
 }
+
+#[cfg(feature = "asm")]
+#[unsafe(no_mangle)]
+pub fn observe_i64(h: &Histogram<&[i64], { cfg!(feature = "unsafe") }>, v: i64) {
+    h.observe(v)
+}
@@ -0,0 +1,19 @@
+/// Creates a [`Histogram`](crate::Histogram) with common bucket schemas:
+/// - `histogram!(linear: start, width, count)`: `count` buckets `start + width * i`;
+/// - `histogram!(exponential: start, factor, count)`: [`LogBuckets`](crate::LogBuckets);
+/// - `histogram!(buckets: [b0, b1, ...])`: explicit buckets, converted into a `Vec`.
+#[macro_export]
+macro_rules! histogram {
+    (linear: $start:expr, $width:expr, $count:expr $(,)?) => {{
+        let (start, width): (f64, f64) = ($start, $width);
+        $crate::Histogram::new(::std::iter::Iterator::collect::<::std::vec::Vec<f64>>(
+            ::std::iter::Iterator::map(0..$count, |i: usize| start + width * i as f64),
+        ))
+    }};
+    (exponential: $start:expr, $factor:expr, $count:expr $(,)?) => {
+        $crate::Histogram::new($crate::LogBuckets::new($start, $factor, $count))
+    };
+    (buckets: $buckets:expr $(,)?) => {
+        $crate::Histogram::new(::std::vec::Vec::from($buckets))
+    };
+}
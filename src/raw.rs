@@ -0,0 +1,53 @@
+use crate::{Histogram, HistogramBuckets, HistogramCounters, Ordering, Shard, ShardId};
+
+/// Direct read access to the counters of a histogram, returned by
+/// [`Histogram::raw_atomics`].
+///
+/// Counters are loaded without swapping shards nor waiting for in-flight observations,
+/// so reads are racy: the count, the sum and the bucket counts of a shard may not be
+/// consistent with each other.
+#[derive(Debug)]
+pub struct RawHandle<'a, B: HistogramBuckets, const TRUSTED_BUCKETS: bool = false>(
+    &'a Histogram<B, TRUSTED_BUCKETS>,
+);
+
+impl<B: HistogramBuckets, const TRUSTED_BUCKETS: bool> RawHandle<'_, B, TRUSTED_BUCKETS> {
+    fn shard(&self, shard: ShardId) -> &Shard<B> {
+        &self.0 .0.shards[shard as usize]
+    }
+
+    /// Returns the number of raw buckets, including `+Inf` and `NaN` (if any) buckets.
+    pub fn bucket_count(&self) -> usize {
+        self.0 .0.bucket_count
+    }
+
+    pub fn count(&self, shard: ShardId) -> u64 {
+        let count = self.shard(shard).counters.count().load(Ordering::Acquire);
+        count & !Shard::<B>::WAITING_FLAG
+    }
+
+    pub fn sum(&self, shard: ShardId) -> f64 {
+        Shard::<B>::sum_into_f64(self.shard(shard).load_sum())
+    }
+
+    /// # Panics
+    ///
+    /// `bucket_index` must be lesser than [`bucket_count`](Self::bucket_count).
+    pub fn bucket(&self, shard: ShardId, bucket_index: usize) -> u64 {
+        assert!(
+            bucket_index < self.bucket_count(),
+            "bucket index out of range"
+        );
+        let counter = self.shard(shard).counters.bucket(bucket_index);
+        counter.load(Ordering::Acquire)
+    }
+}
+
+impl<B: HistogramBuckets, const TRUSTED_BUCKETS: bool> Histogram<B, TRUSTED_BUCKETS> {
+    /// Gives direct read access to the counters of both shards, e.g. for custom exporters.
+    ///
+    /// Contrary to [`collect`](Self::collect), reads are not consistent, see [`RawHandle`].
+    pub fn raw_atomics(&self) -> RawHandle<'_, B, TRUSTED_BUCKETS> {
+        RawHandle(self)
+    }
+}
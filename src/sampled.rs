@@ -0,0 +1,83 @@
+use std::{
+    cell::Cell,
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+};
+
+use crate::{Histogram, HistogramBuckets};
+
+/// A histogram recording only one observation out of `rate` on average, for paths too hot
+/// for even an atomic increment per observation.
+///
+/// Observations are sampled randomly with a thread-local xorshift generator, and collected
+/// counts and sum are scaled by `rate`. They are thus estimates: counts are multiples of
+/// `rate`, with a relative standard error of about `1 / sqrt(count / rate)` per bucket, so
+/// buckets with few observations, e.g. tail latencies, are unreliable. Observations must not
+/// be correlated with the sampling, which a random generator ensures unlike a counter.
+#[derive(Debug, Clone)]
+pub struct SampledHistogram<B: HistogramBuckets = Vec<f64>> {
+    histogram: Histogram<B>,
+    rate: u64,
+    // sampled if the random value is lower or equal
+    threshold: u64,
+}
+
+impl<B: HistogramBuckets> SampledHistogram<B> {
+    /// # Panics
+    ///
+    /// `rate` must not be zero.
+    pub fn new(buckets: B, rate: u64) -> Self {
+        assert_ne!(rate, 0, "rate must not be zero");
+        Self {
+            histogram: Histogram::new(buckets),
+            rate,
+            threshold: u64::MAX / rate,
+        }
+    }
+
+    pub fn observe(&self, value: B::Value) {
+        if next_random() <= self.threshold {
+            self.histogram.observe(value);
+        }
+    }
+
+    /// Returns the underlying histogram, with unscaled sampled observations.
+    pub fn histogram(&self) -> &Histogram<B> {
+        &self.histogram
+    }
+
+    /// Same as [`Histogram::collect`], with counts and sum scaled by the sampling rate.
+    #[must_use = "use `rotate` to swap shards without reading them"]
+    pub fn collect(&self) -> (u64, f64, impl Iterator<Item = (f64, u64)>) {
+        let rate = self.rate;
+        let (count, sum, buckets) = self.histogram.collect();
+        let buckets = buckets.map(move |(boundary, count)| (boundary, count.saturating_mul(rate)));
+        (count.saturating_mul(rate), sum * rate as f64, buckets)
+    }
+}
+
+thread_local! {
+    static RANDOM_STATE: Cell<u64> = Cell::new(random_seed());
+}
+
+fn random_seed() -> u64 {
+    // `RandomState` keys are random, and xorshift state must not be zero
+    RandomState::new().build_hasher().finish() | 1
+}
+
+#[cfg(test)]
+pub(crate) fn seed_thread(seed: u64) {
+    RANDOM_STATE.with(|state| state.set(seed | 1));
+}
+
+// xorshift64*
+fn next_random() -> u64 {
+    RANDOM_STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        state.set(x);
+        x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    })
+}
@@ -0,0 +1,90 @@
+use crate::{Histogram, HistogramBuckets, HistogramValue, LogBuckets};
+
+/// A histogram with a companion [DDSketch](https://arxiv.org/abs/1908.10693),
+/// to estimate quantiles with a bounded relative error.
+///
+/// The sketch is itself a histogram with logarithmic buckets, so it benefits from
+/// the same hot/cold shard consistency.
+#[derive(Debug, Clone)]
+pub struct SketchedHistogram<B: HistogramBuckets = Vec<f64>> {
+    histogram: Histogram<B>,
+    sketch: Histogram<LogBuckets>,
+    min: f64,
+    max: f64,
+    gamma: f64,
+}
+
+impl<B: HistogramBuckets> SketchedHistogram<B>
+where
+    B::Value: Clone,
+{
+    /// Estimated quantiles of values in `min..=max` have a relative error bounded
+    /// by `relative_accuracy`; values outside this range are clamped.
+    ///
+    /// # Panics
+    ///
+    /// `relative_accuracy` must be in `0.0..1.0`, and `0.0 < min < max`.
+    pub fn new(buckets: B, relative_accuracy: f64, min: f64, max: f64) -> Self {
+        assert!(
+            relative_accuracy > 0.0 && relative_accuracy < 1.0,
+            "relative accuracy must be in 0.0..1.0"
+        );
+        assert!(0.0 < min && min < max, "invalid value range");
+        let gamma = (1.0 + relative_accuracy) / (1.0 - relative_accuracy);
+        let count = ((max / min).ln() / gamma.ln()).ceil() as usize + 1;
+        Self {
+            histogram: Histogram::new(buckets),
+            sketch: Histogram::new(LogBuckets::new(min, gamma, count)),
+            min,
+            max,
+            gamma,
+        }
+    }
+
+    pub fn observe(&self, value: B::Value) {
+        self.sketch.observe(value.clone().into_f64());
+        self.histogram.observe(value);
+    }
+
+    pub fn histogram(&self) -> &Histogram<B> {
+        &self.histogram
+    }
+
+    /// Same as [`Histogram::collect`].
+    #[must_use = "use `rotate` to swap shards without reading them"]
+    pub fn collect(&self) -> (u64, f64, impl Iterator<Item = (f64, u64)>) {
+        self.histogram.collect()
+    }
+
+    /// Estimates the `q`-quantile of observed values, `NaN` excluded, or returns `None`
+    /// if there is none.
+    ///
+    /// # Panics
+    ///
+    /// `q` must be in `0.0..=1.0`.
+    #[must_use]
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        assert!((0.0..=1.0).contains(&q), "quantile must be in 0.0..=1.0");
+        // `NaN` observations are in the count but in no bucket, so they are not ranked
+        let (_, _, buckets) = self.sketch.collect();
+        let buckets = buckets.collect::<Vec<_>>();
+        let count = buckets.iter().map(|(_, count)| count).sum::<u64>();
+        if count == 0 {
+            return None;
+        }
+        let rank = q * (count - 1) as f64;
+        let mut cumulative = 0;
+        for (i, (boundary, bucket_count)) in buckets.into_iter().enumerate() {
+            cumulative += bucket_count;
+            if cumulative as f64 > rank {
+                return Some(match i {
+                    0 => self.min,
+                    // bucket `(boundary / gamma, boundary]` midpoint in relative terms
+                    _ if boundary.is_finite() => 2.0 * boundary / (self.gamma + 1.0),
+                    _ => self.max,
+                });
+            }
+        }
+        Some(self.max)
+    }
+}
@@ -0,0 +1,419 @@
+#[cfg(feature = "publish")]
+use std::sync::Arc;
+use std::{collections::BTreeMap, error, fmt, iter};
+
+use ordered_float::OrderedFloat;
+
+use crate::{Histogram, HistogramBuckets, HistogramValue, ShardDiag};
+
+#[derive(Debug, Clone)]
+pub struct HistogramSnapshot {
+    pub count: u64,
+    pub sum: f64,
+    pub buckets: Vec<(f64, u64)>,
+}
+
+// a `NaN` sum, e.g. after observing `NaN`, is equal to itself, so that snapshots are `Eq`
+impl PartialEq for HistogramSnapshot {
+    fn eq(&self, other: &Self) -> bool {
+        self.approx_eq(other, 0.0)
+    }
+}
+
+impl Eq for HistogramSnapshot {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The input ends before the end of the snapshot.
+    Truncated,
+    /// The input continues after the end of the snapshot.
+    TrailingBytes,
+    UnsupportedVersion(u8),
+    /// Bucket counts exceed the total count.
+    InvalidCount,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Truncated => write!(f, "truncated snapshot"),
+            Self::TrailingBytes => write!(f, "trailing bytes after snapshot"),
+            Self::UnsupportedVersion(version) => write!(f, "unsupported version {version}"),
+            Self::InvalidCount => write!(f, "bucket counts exceed the total count"),
+        }
+    }
+}
+
+impl error::Error for DecodeError {}
+
+/// Error returned by [`HistogramSnapshot::rebucket`] and
+/// [`HistogramSnapshot::merge_compatible`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IncompatibleBoundaries;
+
+impl fmt::Display for IncompatibleBoundaries {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "boundaries are not a sorted subset of the other ones")
+    }
+}
+
+impl error::Error for IncompatibleBoundaries {}
+
+/// Output of [`Histogram::collect_struct`].
+#[derive(Debug, Clone)]
+pub struct Collected<I> {
+    pub count: u64,
+    pub sum: f64,
+    pub buckets: I,
+}
+
+impl<I: Iterator<Item = (f64, u64)>> Collected<I> {
+    /// Returns the mean of observed values, or `None` if there is none.
+    #[must_use]
+    pub fn mean(&self) -> Option<f64> {
+        (self.count != 0).then(|| self.sum / self.count as f64)
+    }
+
+    /// Converts bucket counts into cumulative counts, as exposed by Prometheus.
+    #[must_use]
+    pub fn cumulative(self) -> Collected<impl Iterator<Item = (f64, u64)>> {
+        let buckets = self.buckets.scan(0, |cumulative, (boundary, count)| {
+            *cumulative += count;
+            Some((boundary, *cumulative))
+        });
+        Collected {
+            count: self.count,
+            sum: self.sum,
+            buckets,
+        }
+    }
+}
+
+/// Output of [`Histogram::collect_full`].
+#[derive(Debug, Clone)]
+pub struct CollectedFull {
+    pub count: u64,
+    pub sum: f64,
+    pub raw_buckets: Vec<(f64, u64)>,
+    pub cumulative_buckets: Vec<(f64, u64)>,
+    /// Count of `NaN` observations, included in `count` but in no bucket.
+    pub nan_count: u64,
+}
+
+impl HistogramSnapshot {
+    const VERSION: u8 = 1;
+
+    /// Encodes the snapshot in a compact little-endian binary format:
+    /// - version (`u8`)
+    /// - bucket count (`u32`)
+    /// - for each bucket, its boundary (`f64`) and its count (`u64`)
+    /// - sum (`f64`)
+    /// - count (`u64`)
+    ///
+    /// Floats are encoded with their bit representation, so `+Inf` boundary
+    /// and `NaN` sum are preserved.
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(1 + 4 + 16 * self.buckets.len() + 16);
+        bytes.push(Self::VERSION);
+        let bucket_count = u32::try_from(self.buckets.len()).expect("too many buckets");
+        bytes.extend(bucket_count.to_le_bytes());
+        for (boundary, count) in &self.buckets {
+            bytes.extend(boundary.to_bits().to_le_bytes());
+            bytes.extend(count.to_le_bytes());
+        }
+        bytes.extend(self.sum.to_bits().to_le_bytes());
+        bytes.extend(self.count.to_le_bytes());
+        bytes
+    }
+
+    /// Decodes a snapshot encoded with [`to_bytes`](Self::to_bytes).
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        fn read<const N: usize>(bytes: &mut &[u8]) -> Result<[u8; N], DecodeError> {
+            let (chunk, rest) = bytes.split_first_chunk().ok_or(DecodeError::Truncated)?;
+            *bytes = rest;
+            Ok(*chunk)
+        }
+        let mut bytes = bytes;
+        let [version] = read(&mut bytes)?;
+        if version != Self::VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        let bucket_count = u32::from_le_bytes(read(&mut bytes)?) as usize;
+        // checked before allocating, as the bucket count is not trusted; a length
+        // overflowing `usize`, e.g. on 32-bit targets, cannot be in the input
+        let len = (bucket_count.checked_mul(16))
+            .and_then(|len| len.checked_add(16))
+            .ok_or(DecodeError::Truncated)?;
+        if bytes.len() != len {
+            return Err(match bytes.len() < len {
+                true => DecodeError::Truncated,
+                false => DecodeError::TrailingBytes,
+            });
+        }
+        let mut buckets = Vec::with_capacity(bucket_count);
+        for _ in 0..bucket_count {
+            let boundary = f64::from_bits(u64::from_le_bytes(read(&mut bytes)?));
+            buckets.push((boundary, u64::from_le_bytes(read(&mut bytes)?)));
+        }
+        let sum = f64::from_bits(u64::from_le_bytes(read(&mut bytes)?));
+        let count = u64::from_le_bytes(read(&mut bytes)?);
+        // `NaN` observations are only in the total count
+        let bucket_total = buckets
+            .iter()
+            .try_fold(0u64, |acc, (_, c)| acc.checked_add(*c));
+        if bucket_total.is_none_or(|total| total > count) {
+            return Err(DecodeError::InvalidCount);
+        }
+        Ok(Self {
+            count,
+            sum,
+            buckets,
+        })
+    }
+
+    /// Returns bucket counts keyed by boundary, the last key being `+Inf`.
+    #[must_use]
+    pub fn as_map(&self) -> BTreeMap<OrderedFloat<f64>, u64> {
+        (self.buckets.iter())
+            .map(|(boundary, count)| (OrderedFloat(*boundary), *count))
+            .collect()
+    }
+
+    /// Same as `==`, but with sums only required to be within `sum_epsilon`, e.g. to
+    /// compare against expected snapshots despite rounding errors.
+    ///
+    /// Bucket boundaries and counts are still compared exactly.
+    #[must_use]
+    pub fn approx_eq(&self, other: &Self, sum_epsilon: f64) -> bool {
+        let bucket = |&(boundary, count): &(f64, u64)| (OrderedFloat(boundary), count);
+        // infinite sums would otherwise never be equal, as their difference is `NaN`
+        let sum_eq = OrderedFloat(self.sum) == OrderedFloat(other.sum)
+            || (self.sum - other.sum).abs() <= sum_epsilon;
+        self.count == other.count
+            && sum_eq
+            && (self.buckets.iter().map(bucket)).eq(other.buckets.iter().map(bucket))
+    }
+
+    /// Merges adjacent buckets into the coarser `new_boundaries`.
+    ///
+    /// Returns an error if `new_boundaries` are not sorted nor a subset of the snapshot
+    /// boundaries, as counts cannot be redistributed otherwise.
+    pub fn rebucket(
+        &self,
+        new_boundaries: &[f64],
+    ) -> Result<HistogramSnapshot, IncompatibleBoundaries> {
+        let mut boundaries = self.buckets.iter().map(|(b, _)| *b);
+        for new in new_boundaries {
+            if boundaries.find(|b| b >= new) != Some(*new) {
+                return Err(IncompatibleBoundaries);
+            }
+        }
+        let mut buckets = (new_boundaries.iter().copied())
+            .filter(|b| *b != f64::INFINITY)
+            .chain([f64::INFINITY])
+            .map(|b| (b, 0))
+            .collect::<Vec<_>>();
+        let mut new_buckets = buckets.iter_mut();
+        let mut new_bucket = new_buckets.next().unwrap();
+        for (boundary, count) in &self.buckets {
+            while *boundary > new_bucket.0 {
+                new_bucket = new_buckets.next().unwrap();
+            }
+            new_bucket.1 += count;
+        }
+        Ok(HistogramSnapshot {
+            count: self.count,
+            sum: self.sum,
+            buckets,
+        })
+    }
+
+    /// Merges two snapshots whose boundaries are a subset of one another, e.g. from
+    /// services with refined buckets, by [rebucketing](Self::rebucket) the finer one
+    /// into the coarser boundaries.
+    pub fn merge_compatible(&self, other: &Self) -> Result<Self, IncompatibleBoundaries> {
+        fn boundaries(snapshot: &HistogramSnapshot) -> Vec<f64> {
+            snapshot.buckets.iter().map(|(b, _)| *b).collect()
+        }
+        let (coarse, rebucketed) = match other.rebucket(&boundaries(self)) {
+            Ok(rebucketed) => (self, rebucketed),
+            Err(_) => (other, self.rebucket(&boundaries(other))?),
+        };
+        Ok(HistogramSnapshot {
+            count: coarse.count + rebucketed.count,
+            sum: coarse.sum + rebucketed.sum,
+            buckets: iter::zip(&coarse.buckets, rebucketed.buckets)
+                .map(|((boundary, count), (_, other))| (*boundary, count + other))
+                .collect(),
+        })
+    }
+}
+
+impl<B: HistogramBuckets> Histogram<B> {
+    /// Builds a one-shot histogram from the given values and returns its snapshot.
+    #[must_use]
+    pub fn collect_from(
+        buckets: B,
+        values: impl IntoIterator<Item = B::Value>,
+    ) -> HistogramSnapshot {
+        let histogram = Self::new(buckets);
+        histogram.observe_many(values);
+        histogram.snapshot()
+    }
+}
+
+impl<B: HistogramBuckets, const TRUSTED_BUCKETS: bool> Histogram<B, TRUSTED_BUCKETS> {
+    /// Same as [`collect`](Self::collect), but returns a [`Collected`] struct.
+    #[must_use]
+    pub fn collect_struct(&self) -> Collected<impl Iterator<Item = (f64, u64)>> {
+        let (count, sum, buckets) = self.collect();
+        Collected {
+            count,
+            sum,
+            buckets,
+        }
+    }
+
+    /// Same as [`collect_struct`](Self::collect_struct), but also returns the
+    /// [`ShardDiag`] of each shard, indexed by [`ShardId`](crate::ShardId).
+    ///
+    /// A [paced](crate::HistogramBuilder::min_collect_interval) collection returns the
+    /// diagnostics of the cached one.
+    #[must_use]
+    pub fn collect_with_diag(
+        &self,
+    ) -> (Collected<impl Iterator<Item = (f64, u64)>>, [ShardDiag; 2]) {
+        let raw = {
+            let mut state = self.lock_collector();
+            self.collect_raw_paced(&mut state)
+        };
+        let collected = Collected {
+            count: raw.count,
+            sum: raw.sum,
+            buckets: self.with_boundaries(raw.counts),
+        };
+        (collected, raw.diags)
+    }
+
+    /// Same as [`collect`](Self::collect), but returns both raw and cumulative
+    /// bucket counts, computed in a single pass, as well as the count of `NaN` observations.
+    #[must_use]
+    pub fn collect_full(&self) -> CollectedFull {
+        let raw = {
+            let mut state = self.lock_collector();
+            self.collect_raw_paced(&mut state)
+        };
+        let mut counts = raw.counts;
+        let nan_count = match B::Value::HAS_NAN {
+            true => counts.pop().expect("the `NaN` bucket is the last one"),
+            false => 0,
+        };
+        let (raw_buckets, cumulative_buckets) = (self.with_boundaries(counts))
+            .scan(0, |cumulative, (boundary, count)| {
+                *cumulative += count;
+                Some(((boundary, count), (boundary, *cumulative)))
+            })
+            .unzip();
+        CollectedFull {
+            count: raw.count,
+            sum: raw.sum,
+            raw_buckets,
+            cumulative_buckets,
+            nan_count,
+        }
+    }
+
+    /// Same as [`collect`](Self::collect), but only returns cumulative bucket counts,
+    /// computed lazily.
+    ///
+    /// The `+Inf` bucket count is the total count, except for `NaN` observations, which
+    /// are not counted in buckets.
+    #[must_use = "use `rotate` to swap shards without reading them"]
+    pub fn collect_cumulative_iter(&self) -> impl Iterator<Item = (f64, u64)> {
+        self.collect_struct().cumulative().buckets
+    }
+
+    #[must_use]
+    pub fn snapshot(&self) -> HistogramSnapshot {
+        let (count, sum, buckets) = self.collect();
+        HistogramSnapshot {
+            count,
+            sum,
+            buckets: buckets.collect(),
+        }
+    }
+
+    /// Same as [`snapshot`](Self::snapshot), but also resets the histogram like
+    /// [`collect_and_reset`](Self::collect_and_reset), so that each snapshot only covers
+    /// the observations since the previous one, e.g. for push-based reporting.
+    #[must_use = "use `reset` to discard the collected data"]
+    pub fn drain(&self) -> HistogramSnapshot {
+        let (count, sum, buckets) = self.collect_and_reset();
+        HistogramSnapshot {
+            count,
+            sum,
+            buckets: buckets.collect(),
+        }
+    }
+
+    /// Collects the histogram and returns the increment since `baseline`, e.g. to compute rates.
+    ///
+    /// If any counter is lower than in `baseline`, the histogram is considered to have been
+    /// reset, and the current snapshot is returned as is, like Prometheus does for counter
+    /// resets.
+    ///
+    /// # Panics
+    ///
+    /// `baseline` must have the same boundaries as the histogram.
+    #[must_use]
+    pub fn increment_since(&self, baseline: &HistogramSnapshot) -> HistogramSnapshot {
+        let current = self.snapshot();
+        assert!(
+            current.buckets.len() == baseline.buckets.len()
+                && iter::zip(&current.buckets, &baseline.buckets).all(|(a, b)| a.0 == b.0),
+            "baseline boundaries must match the histogram ones"
+        );
+        let reset = current.count < baseline.count
+            || iter::zip(&current.buckets, &baseline.buckets).any(|(a, b)| a.1 < b.1);
+        if reset {
+            return current;
+        }
+        HistogramSnapshot {
+            count: current.count - baseline.count,
+            sum: current.sum - baseline.sum,
+            buckets: iter::zip(current.buckets, &baseline.buckets)
+                .map(|((boundary, count), (_, base))| (boundary, count - base))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(feature = "publish")]
+impl<B: HistogramBuckets, const TRUSTED_BUCKETS: bool> Histogram<B, TRUSTED_BUCKETS> {
+    /// Collects the histogram and publishes the resulting snapshot, which can then be read
+    /// with [`published`](Self::published).
+    ///
+    /// Publication can be done on a cadence, e.g. by a background task, so that exporters
+    /// never have to wait for in-flight observations.
+    pub fn publish(&self) -> Arc<HistogramSnapshot> {
+        // publish while holding the collector lock, so that concurrent publications
+        // cannot overwrite a snapshot with an older one
+        let mut state = self.lock_collector();
+        let (count, sum, buckets, _) = self.collect_locked(&mut state);
+        let snapshot = Arc::new(HistogramSnapshot {
+            count,
+            sum,
+            buckets: buckets.collect(),
+        });
+        self.0.published.store(Some(snapshot.clone()));
+        snapshot
+    }
+
+    /// Returns the last snapshot published with [`publish`](Self::publish), if any.
+    ///
+    /// Contrary to [`collect`](Self::collect), it never spins nor blocks.
+    pub fn published(&self) -> Option<Arc<HistogramSnapshot>> {
+        self.0.published.load_full()
+    }
+}
@@ -12,13 +12,30 @@ fn model(f: impl Fn()) {
     f();
 }
 
+// the `f64` sum is not accumulated with `f64-no-sum`, so it is always zero
+#[cfg(not(feature = "f64-no-sum"))]
+fn f64_sum(sum: f64) -> f64 {
+    sum
+}
+#[cfg(feature = "f64-no-sum")]
+fn f64_sum(_sum: f64) -> f64 {
+    0.0
+}
+
+#[track_caller]
+fn assert_f64_sum(actual: f64, expected: f64) {
+    let expected = f64_sum(expected);
+    let eq = actual == expected || (actual.is_nan() && expected.is_nan());
+    assert!(eq, "sum mismatch: {actual} != {expected}");
+}
+
 #[test]
 fn observe_and_collect() {
     model(move || {
         #[cfg(not(feature = "unsafe"))]
-        let histogram = Histogram::new(&[10, 100]);
+        let histogram = Histogram::new(&[10u64, 100]);
         #[cfg(feature = "unsafe")]
-        let histogram = Histogram::new_trusted(&[10, 100]);
+        let histogram = Histogram::new_trusted(&[10u64, 100]);
         let h1 = histogram.clone();
         let h2 = histogram.clone();
         let t1 = thread::spawn(move || h1.observe(42));
@@ -56,7 +73,7 @@ fn double_collect_edge_case() {
     let edge_case = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
     let edge_case2 = edge_case.clone();
     model(move || {
-        let histogram = Histogram::new(vec![10, 100]);
+        let histogram = Histogram::new(vec![10u64, 100]);
         let h1 = histogram.clone();
         let t1 = thread::spawn(move || {
             h1.observe(7);
@@ -83,7 +100,7 @@ fn observe_inf() {
     histogram.observe(1.0);
     let (count, sum, buckets) = histogram.collect();
     assert_eq!(count, 2);
-    assert_eq!(sum, f64::INFINITY);
+    assert_f64_sum(sum, f64::INFINITY);
     assert_eq!(buckets.collect_vec(), vec![(1.0, 1), (f64::INFINITY, 1)]);
 }
 
@@ -94,6 +111,2641 @@ fn observe_nan() {
     histogram.observe(f64::NAN);
     let (count, sum, buckets) = histogram.collect();
     assert_eq!(count, 1);
-    assert!(sum.is_nan());
+    assert_f64_sum(sum, f64::NAN);
     assert_eq!(buckets.collect_vec(), vec![(1.0, 0), (f64::INFINITY, 0)]);
 }
+
+#[cfg(not(loom))]
+#[test]
+fn rebucket() {
+    let histogram = Histogram::new(vec![1.0, 2.0, 3.0, 4.0]);
+    for value in [0.5, 1.5, 2.5, 3.0, 3.5, 5.0] {
+        histogram.observe(value);
+    }
+    let snapshot = histogram.snapshot().rebucket(&[2.0, 4.0]).unwrap();
+    assert_eq!(snapshot.count, 6);
+    assert_f64_sum(snapshot.sum, 16.0);
+    assert_eq!(
+        snapshot.buckets,
+        vec![(2.0, 2), (4.0, 3), (f64::INFINITY, 1)]
+    );
+}
+
+#[cfg(not(loom))]
+#[test]
+fn rebucket_not_coarsening() {
+    use crate::IncompatibleBoundaries;
+
+    let histogram = Histogram::new(vec![1.0, 2.0, 3.0, 4.0]);
+    let snapshot = histogram.snapshot();
+    assert_eq!(snapshot.rebucket(&[2.5]), Err(IncompatibleBoundaries));
+    assert_eq!(snapshot.rebucket(&[4.0, 2.0]), Err(IncompatibleBoundaries));
+}
+
+#[cfg(not(loom))]
+#[test]
+fn btree_set_buckets() {
+    use std::collections::BTreeSet;
+
+    use crate::HistogramBuckets;
+
+    let buckets = BTreeSet::from([100u64, 10, 50]);
+    assert_eq!(buckets.values().collect_vec(), vec![10, 50, 100]);
+    assert_eq!(buckets.bucket_index(&0), Some(0));
+    assert_eq!(buckets.bucket_index(&10), Some(0));
+    assert_eq!(buckets.bucket_index(&11), Some(1));
+    assert_eq!(buckets.bucket_index(&100), Some(2));
+    assert_eq!(buckets.bucket_index(&101), None);
+    let histogram = Histogram::new(buckets);
+    for value in [5, 42, 42, 1000] {
+        histogram.observe(value);
+    }
+    let (count, sum, buckets) = histogram.collect();
+    assert_eq!(count, 4);
+    assert_eq!(sum, 1089.0);
+    assert_eq!(
+        buckets.collect_vec(),
+        vec![(10.0, 1), (50.0, 2), (100.0, 0), (f64::INFINITY, 1)]
+    );
+}
+
+#[cfg(not(loom))]
+#[test]
+fn read_shard() {
+    use crate::ShardId;
+
+    let histogram = Histogram::new(vec![10u64]);
+    assert_eq!(histogram.hot_shard(), ShardId::First);
+    histogram.observe(1);
+    assert_eq!(histogram.read_shard(ShardId::First), (1, 1.0, vec![1, 0]));
+    assert_eq!(histogram.read_shard(ShardId::Second), (0, 0.0, vec![0, 0]));
+    let _ = histogram.collect();
+    assert_eq!(histogram.hot_shard(), ShardId::Second);
+    histogram.observe(20);
+    histogram.observe(30);
+    assert_eq!(histogram.read_shard(ShardId::First), (1, 1.0, vec![1, 0]));
+    assert_eq!(histogram.read_shard(ShardId::Second), (2, 50.0, vec![0, 2]));
+}
+
+#[cfg(not(loom))]
+#[test]
+fn observe_duration() {
+    use std::time::Duration;
+
+    use crate::HistogramValue;
+
+    let histogram = Histogram::new(vec![Duration::from_micros(100), Duration::from_millis(1)]);
+    let mut total_nanos = 0;
+    for i in 0..10_000 {
+        let nanos = 99_000 + 7 * i;
+        histogram.observe(Duration::from_nanos(nanos));
+        total_nanos += nanos;
+    }
+    let (count, sum, buckets) = histogram.collect();
+    assert_eq!(count, 10_000);
+    assert_eq!(sum, Duration::from_nanos(total_nanos).as_secs_f64());
+    assert_eq!(
+        buckets.collect_vec(),
+        vec![(0.0001, 143), (0.001, 9857), (f64::INFINITY, 0)]
+    );
+    // imported sums out of `Duration` range saturate
+    let histogram = Histogram::new(vec![Duration::from_secs(1)]);
+    histogram.import(&[0, 0], -1.0, 0);
+    histogram.import(&[0, 0], f64::NAN, 0);
+    assert_eq!(histogram.collect().1, 0.0);
+    assert_eq!(Duration::from_f64(f64::INFINITY), Duration::MAX);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn max_bucket_count() {
+    use std::sync::atomic::Ordering;
+
+    use crate::HistogramCounters;
+
+    let histogram = Histogram::new(vec![10u64]);
+    let max_shard_count = (1 << 63) - 1;
+    for shard in &histogram.0.shards {
+        shard
+            .counters
+            .count()
+            .store(max_shard_count, Ordering::Relaxed);
+        (shard.counters.bucket(0)).store(max_shard_count, Ordering::Relaxed);
+    }
+    let (count, _, buckets) = histogram.collect();
+    assert_eq!(count, u64::MAX - 1);
+    assert_eq!(
+        buckets.collect_vec(),
+        vec![(10.0, u64::MAX - 1), (f64::INFINITY, 0)]
+    );
+}
+
+#[cfg(not(loom))]
+#[test]
+fn subnormal_values() {
+    let subnormal = f64::from_bits(1);
+    let histogram = Histogram::new(vec![-subnormal, 0.0, subnormal, f64::MIN_POSITIVE]);
+    histogram.observe_all(&[-subnormal, subnormal, subnormal, f64::MIN_POSITIVE / 2.0]);
+    crate::assert_histogram(
+        &histogram,
+        &[
+            (-subnormal, 1),
+            (0.0, 0),
+            (subnormal, 2),
+            (f64::MIN_POSITIVE, 1),
+            (f64::INFINITY, 0),
+        ],
+        f64_sum(subnormal + f64::MIN_POSITIVE / 2.0),
+    );
+}
+
+#[cfg(not(loom))]
+#[test]
+fn extreme_values() {
+    let histogram = Histogram::new(vec![1.0]);
+    histogram.observe(f64::MAX);
+    crate::assert_histogram(
+        &histogram,
+        &[(1.0, 0), (f64::INFINITY, 1)],
+        f64_sum(f64::MAX),
+    );
+    // overflow to `+Inf`, across shards
+    histogram.observe(f64::MAX);
+    crate::assert_histogram(
+        &histogram,
+        &[(1.0, 0), (f64::INFINITY, 2)],
+        f64_sum(f64::INFINITY),
+    );
+    // non-negative values cannot bring the sum back
+    histogram.observe_all(&[1.0, f64::MAX, 0.0]);
+    crate::assert_histogram(
+        &histogram,
+        &[(1.0, 2), (f64::INFINITY, 3)],
+        f64_sum(f64::INFINITY),
+    );
+    histogram.observe(f64::MIN_POSITIVE);
+    crate::assert_histogram(
+        &histogram,
+        &[(1.0, 3), (f64::INFINITY, 3)],
+        f64_sum(f64::INFINITY),
+    );
+    // `+Inf` is also preserved when summing within a single observation
+    let (_, sum, _) = histogram.collect_and_reset();
+    assert_f64_sum(sum, f64::INFINITY);
+    histogram.observe_many([f64::MAX, f64::MAX]);
+    assert_f64_sum(histogram.collect().1, f64::INFINITY);
+    histogram.reset();
+    histogram.observe(f64::MIN);
+    histogram.observe(f64::MIN);
+    assert_f64_sum(histogram.collect().1, f64::NEG_INFINITY);
+    // only mixing infinities gives `NaN`
+    histogram.observe(f64::INFINITY);
+    assert_f64_sum(histogram.collect().1, f64::NAN);
+    histogram.reset();
+    histogram.observe(1.0);
+    crate::assert_histogram(&histogram, &[(1.0, 1), (f64::INFINITY, 0)], f64_sum(1.0));
+}
+
+#[cfg(not(loom))]
+#[test]
+fn assert_histogram() {
+    let histogram = Histogram::new(vec![1.0]);
+    histogram.observe(0.5);
+    histogram.observe(f64::NAN);
+    crate::assert_histogram(
+        &histogram,
+        &[(1.0, 1), (f64::INFINITY, 0)],
+        f64_sum(f64::NAN),
+    );
+}
+
+#[cfg(not(loom))]
+#[test]
+#[should_panic = "histogram mismatch (expected | actual):
+  le=1                   1 | le=1                   1
+> le=2                   0 | le=+Inf                2
+> le=+Inf                2 | le=-                   -
+> sum=4 | sum=5"]
+fn assert_histogram_mismatch() {
+    let histogram = Histogram::new(vec![1u64]);
+    for value in [1, 2, 2] {
+        histogram.observe(value);
+    }
+    crate::assert_histogram(&histogram, &[(1.0, 1), (2.0, 0), (f64::INFINITY, 2)], 4.0);
+}
+
+#[cfg(loom)]
+#[test]
+fn collect_sum_consistency() {
+    model(|| {
+        let histogram = Histogram::new(vec![1u64, 2]);
+        let h1 = histogram.clone();
+        let t1 = thread::spawn(move || {
+            h1.observe(1);
+            h1.observe(2);
+        });
+        let (count, sum, buckets) = histogram.collect();
+        let [(1.0, b0), (2.0, b1), (f64::INFINITY, 0)] = buckets.collect_vec()[..] else {
+            unreachable!()
+        };
+        assert_eq!(count, b0 + b1);
+        assert_eq!(sum, (b0 + 2 * b1) as f64);
+        t1.join().unwrap();
+    });
+}
+
+#[cfg(not(loom))]
+#[test]
+fn native_buckets() {
+    use crate::{HistogramBuckets, NativeBuckets};
+
+    let buckets = NativeBuckets::new(0, 0, 4);
+    assert_eq!(
+        buckets.values().collect_vec(),
+        vec![1.0, 2.0, 4.0, 8.0, 16.0]
+    );
+    assert_eq!(buckets.bucket_index(&-1.0), Some(0));
+    assert_eq!(buckets.bucket_index(&0.5), Some(0));
+    assert_eq!(buckets.bucket_index(&4.0), Some(2));
+    assert_eq!(buckets.bucket_index(&4.000001), Some(3));
+    assert_eq!(buckets.bucket_index(&17.0), None);
+    assert_eq!(buckets.bucket_index(&f64::INFINITY), None);
+    assert_eq!(buckets.bucket_index(&f64::NAN), None);
+    for schema in -4..=8 {
+        let buckets = NativeBuckets::new(schema, -20, 20);
+        for (i, boundary) in buckets.values().enumerate() {
+            assert_eq!(buckets.bucket_index(&boundary), Some(i));
+            let above = f64::from_bits(boundary.to_bits() + 1);
+            assert_eq!(
+                buckets.bucket_index(&above),
+                Some(i + 1).filter(|i| *i <= 40)
+            );
+        }
+    }
+}
+
+#[cfg(not(loom))]
+#[test]
+fn log_buckets() {
+    use crate::{HistogramBuckets, LogBuckets};
+
+    for (start, factor, count) in [
+        (1.0, 2.0, 1),
+        (0.001, 2.0, 20),
+        (0.5, 1.1, 100),
+        (3.0, 10.0, 8),
+    ] {
+        let buckets = LogBuckets::new(start, factor, count);
+        let values = (0..count)
+            .map(|i| start * factor.powi(i as i32))
+            .collect_vec();
+        assert_eq!(buckets.values().collect_vec(), values);
+        let mut probes = vec![-1.0, 0.0, f64::MIN_POSITIVE, f64::INFINITY, f64::NAN];
+        for boundary in &values {
+            let bits = boundary.to_bits();
+            probes.extend([
+                f64::from_bits(bits - 1),
+                *boundary,
+                f64::from_bits(bits + 1),
+            ]);
+        }
+        for probe in probes {
+            assert_eq!(
+                buckets.bucket_index(&probe),
+                values.bucket_index(&probe),
+                "{probe}"
+            );
+        }
+    }
+}
+
+#[cfg(not(loom))]
+#[test]
+fn histogram_vec() {
+    use crate::HistogramVec;
+
+    let histograms = HistogramVec::with_capacity(vec![1.0], 16);
+    assert!(histograms.capacity() >= 16);
+    assert!(histograms.is_empty());
+    histograms.get_or_create(&"GET").observe(0.5);
+    histograms.get_or_create(&"POST").observe(2.0);
+    let (count, sum, _) = histograms.get_or_create(&"GET").collect();
+    assert_eq!((count, sum), (1, f64_sum(0.5)));
+    assert_eq!(histograms.len(), 2);
+    let removed = histograms.remove(&"POST").unwrap();
+    assert_eq!(removed.collect().0, 1);
+    assert_eq!(histograms.len(), 1);
+    assert!(histograms.remove(&"POST").is_none());
+    assert_eq!(histograms.get_or_create(&"POST").collect().0, 0);
+    assert_eq!(histograms.len(), 2);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn histogram_vec_poisoned() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    use crate::HistogramVec;
+
+    let histograms = HistogramVec::new(vec![1.0]);
+    histograms.get_or_create(&"GET").observe(0.5);
+    let _ = catch_unwind(AssertUnwindSafe(|| {
+        let _guard = histograms.series.write().unwrap();
+        panic!("poison");
+    }));
+    assert!(histograms.series.is_poisoned());
+    assert_eq!(histograms.get_or_create(&"GET").collect().0, 1);
+    assert_eq!(histograms.get_or_create(&"POST").collect().0, 0);
+    assert_eq!(histograms.len(), 2);
+    assert!(histograms.remove(&"POST").is_some());
+}
+
+#[cfg(loom)]
+#[test]
+fn collect_stats_no_lost_observation() {
+    model(|| {
+        let histogram = Histogram::new(vec![1u64, 2]);
+        let h1 = histogram.clone();
+        let t1 = thread::spawn(move || {
+            h1.observe(1);
+            h1.observe(2);
+        });
+        let (count1, _, _, stats1) = histogram.collect_with_stats();
+        let (count2, _, _, stats2) = histogram.collect_with_stats();
+        assert_eq!((stats1.generation, stats2.generation), (1, 2));
+        assert_eq!(count1, stats1.cold_count + stats1.hot_count);
+        assert!(count1 <= count2);
+        t1.join().unwrap();
+        let (count, sum, _, stats) = histogram.collect_with_stats();
+        assert_eq!((count, sum, stats.generation), (2, 3.0, 3));
+    });
+}
+
+#[cfg(not(loom))]
+#[test]
+fn observe_i64() {
+    let histogram = Histogram::new(vec![-10i64, 0, 10]);
+    for value in [-20, -5, 0, 5, 20] {
+        histogram.observe(value);
+    }
+    histogram.observe(-42);
+    let (count, sum, buckets) = histogram.collect();
+    assert_eq!(count, 6);
+    assert_eq!(sum, -42.0);
+    assert_eq!(
+        buckets.collect_vec(),
+        vec![(-10.0, 2), (0.0, 2), (10.0, 1), (f64::INFINITY, 1)]
+    );
+}
+
+#[cfg(not(loom))]
+#[test]
+fn import() {
+    let histogram = Histogram::new(vec![10u64, 100]);
+    for value in [5, 50, 50, 500] {
+        histogram.observe(value);
+    }
+    let (count, sum, buckets) = histogram.collect();
+    let counts = buckets.map(|(_, c)| c).collect_vec();
+    let imported = Histogram::new(vec![10u64, 100]);
+    imported.import(&counts, sum, count);
+    imported.observe(1);
+    let (count, sum, buckets) = imported.collect();
+    assert_eq!((count, sum), (5, 606.0));
+    assert_eq!(
+        buckets.collect_vec(),
+        vec![(10.0, 2), (100.0, 2), (f64::INFINITY, 1)]
+    );
+}
+
+#[cfg(not(loom))]
+#[test]
+fn import_f64() {
+    let histogram = Histogram::new(vec![1.0, 2.0]);
+    histogram.observe_all(&[0.5, 1.5, 3.0, f64::NAN]);
+    let (count, sum, buckets) = histogram.collect();
+    let counts = buckets.map(|(_, c)| c).collect_vec();
+    let imported = Histogram::new(vec![1.0, 2.0]);
+    // `NaN` observations are only in the count
+    imported.import(&counts, sum, count);
+    let (_, _, raw_counts) = imported.read_shard(imported.hot_shard());
+    assert_eq!(raw_counts, [1, 1, 1, 1]);
+    assert_eq!(imported.snapshot(), histogram.snapshot());
+}
+
+#[cfg(not(loom))]
+#[test]
+#[should_panic = "invalid count"]
+fn import_invalid_count() {
+    Histogram::new(vec![10u64]).import(&[1, 2], 3.0, 4);
+}
+
+#[cfg(loom)]
+#[test]
+fn concurrent_collects() {
+    model(|| {
+        let histogram = Histogram::new(vec![1u64, 2]);
+        let h1 = histogram.clone();
+        let h2 = histogram.clone();
+        let t1 = thread::spawn(move || h1.observe(1));
+        let t2 = thread::spawn(move || h2.try_collect().map(|(c, s, _)| (c, s)));
+        let (count, sum, _) = histogram.collect();
+        assert!([(0, 0.0), (1, 1.0)].contains(&(count, sum)));
+        if let Some((count, sum)) = t2.join().unwrap() {
+            assert!([(0, 0.0), (1, 1.0)].contains(&(count, sum)));
+        }
+        t1.join().unwrap();
+        let (count, sum, _) = histogram.collect();
+        assert_eq!((count, sum), (1, 1.0));
+    });
+}
+
+#[cfg(not(loom))]
+#[test]
+fn write_text() {
+    use crate::LeFormat;
+
+    let histogram = Histogram::with_le_format(vec![0.005, 1.0], LeFormat::Fixed(6));
+    histogram.observe(0.001);
+    histogram.observe(2.0);
+    let mut text = String::new();
+    histogram.write_text(&mut text, "latency").unwrap();
+    assert_eq!(
+        text,
+        format!(
+            "# TYPE latency histogram
+latency_bucket{{le=\"0.005000\"}} 1
+latency_bucket{{le=\"1.000000\"}} 1
+latency_bucket{{le=\"+Inf\"}} 2
+latency_sum {:?}
+latency_count 2
+",
+            f64_sum(2.001)
+        )
+    );
+    assert_eq!(LeFormat::Shortest.format(1.0), "1.0");
+    assert_eq!(LeFormat::Shortest.format(0.005), "0.005");
+    assert_eq!(LeFormat::Fixed(2).format(f64::INFINITY), "+Inf");
+    assert_eq!(LeFormat::Shortest.format(f64::NEG_INFINITY), "-Inf");
+}
+
+#[cfg(not(loom))]
+#[test]
+fn write_text_special_sum() {
+    use crate::HistogramSnapshot;
+
+    for (values, sum) in [
+        (&[f64::INFINITY][..], "+Inf"),
+        (&[f64::NEG_INFINITY], "-Inf"),
+        (&[f64::NAN], "NaN"),
+    ] {
+        let histogram = Histogram::new(vec![1.0]);
+        histogram.observe_all(values);
+        let mut text = String::new();
+        histogram.write_text(&mut text, "latency").unwrap();
+        let sum = if cfg!(feature = "f64-no-sum") {
+            "0.0"
+        } else {
+            sum
+        };
+        assert!(text.contains(&format!("\nlatency_sum {sum}\n")), "{text}");
+        let snapshot = HistogramSnapshot::parse_prometheus_text(&text, "latency").unwrap();
+        let expected = histogram.collect().1;
+        assert!(snapshot.sum == expected || snapshot.sum.is_nan() && expected.is_nan());
+    }
+}
+
+#[cfg(not(loom))]
+#[test]
+fn timer_with_mock_clock() {
+    use std::{
+        cell::Cell,
+        time::{Duration, Instant},
+    };
+
+    use crate::Clock;
+
+    struct MockClock(Cell<Instant>);
+    impl Clock for MockClock {
+        fn now(&self) -> Instant {
+            self.0.get()
+        }
+    }
+
+    let clock = MockClock(Cell::new(Instant::now()));
+    let advance = |duration| clock.0.set(clock.0.get() + duration);
+    let histogram = Histogram::new(vec![Duration::from_millis(10)]);
+    let timer = histogram.start_timer_with_clock(&clock);
+    advance(Duration::from_millis(5));
+    assert_eq!(timer.observe_duration(), Duration::from_millis(5));
+    {
+        let _timer = histogram.start_timer_with_clock(&clock);
+        advance(Duration::from_millis(20));
+    }
+    histogram.start_timer_with_clock(&clock).stop_and_discard();
+    let start = clock.now();
+    advance(Duration::from_millis(1));
+    histogram.observe_at(start, clock.now());
+    let (count, sum, buckets) = histogram.collect();
+    assert_eq!((count, sum), (3, 0.026));
+    assert_eq!(buckets.collect_vec(), vec![(0.01, 2), (f64::INFINITY, 1)]);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn observe_duration_since() {
+    use std::time::{Duration, Instant};
+
+    let histogram = Histogram::new(vec![Duration::from_millis(10)]);
+    let start = Instant::now() - Duration::from_millis(20);
+    histogram.observe_duration_since(start);
+    let elapsed = start.elapsed();
+    let (count, sum, buckets) = histogram.collect();
+    assert_eq!(count, 1);
+    assert!((0.02..=elapsed.as_secs_f64()).contains(&sum));
+    assert_eq!(buckets.collect_vec(), vec![(0.01, 0), (f64::INFINITY, 1)]);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn collect_poisoned() {
+    use crate::CollectError;
+
+    let histogram = Histogram::new(vec![1.0]);
+    histogram.observe(0.5);
+    let guard = histogram.0.collector.lock().unwrap();
+    assert_eq!(
+        histogram.try_collect_checked().err(),
+        Some(CollectError::WouldBlock)
+    );
+    drop(guard);
+    let h1 = histogram.clone();
+    thread::spawn(move || {
+        let _guard = h1.0.collector.lock().unwrap();
+        panic!("poison");
+    })
+    .join()
+    .unwrap_err();
+    assert!(histogram.0.collector.is_poisoned());
+    let (count, _, _) = histogram.try_collect_checked().unwrap();
+    assert_eq!(count, 1);
+    let (count, _, _) = histogram.collect();
+    assert_eq!(count, 1);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn observe_ref() {
+    let by_value = Histogram::new(vec![1.0, 10.0]);
+    let by_ref = Histogram::new(vec![1.0, 10.0]);
+    for value in [0.5, 5.0, 50.0, f64::NAN] {
+        by_value.observe(value);
+        by_ref.observe_ref(&value);
+    }
+    let (count, sum, buckets) = by_ref.collect();
+    let (count2, sum2, buckets2) = by_value.collect();
+    assert_eq!(count, count2);
+    assert_eq!(sum.to_bits(), sum2.to_bits());
+    assert_eq!(buckets.collect_vec(), buckets2.collect_vec());
+}
+
+#[cfg(all(feature = "allocator-api2", not(loom)))]
+#[test]
+fn counting_allocator() {
+    use std::{
+        alloc::Layout,
+        ptr::NonNull,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use allocator_api2::alloc::{AllocError, Allocator, Global};
+
+    #[derive(Default)]
+    struct CountingAllocator {
+        allocated: AtomicUsize,
+        deallocated: AtomicUsize,
+    }
+    unsafe impl Allocator for &'static CountingAllocator {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            self.allocated.fetch_add(1, Ordering::Relaxed);
+            Global.allocate(layout)
+        }
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            self.deallocated.fetch_add(1, Ordering::Relaxed);
+            unsafe { Global.deallocate(ptr, layout) }
+        }
+    }
+
+    let alloc = Box::leak(Box::new(CountingAllocator::default()));
+    let histogram = Histogram::new_in(vec![1.0, 2.0], &*alloc);
+    histogram.observe(1.5);
+    assert_eq!(histogram.collect().0, 1);
+    assert_eq!(alloc.allocated.load(Ordering::Relaxed), 2);
+    assert_eq!(alloc.deallocated.load(Ordering::Relaxed), 0);
+    drop(histogram);
+    assert_eq!(alloc.deallocated.load(Ordering::Relaxed), 2);
+    // reallocated counters are freed and allocated with the same allocator
+    let mut histogram = Histogram::new_in((0..1000).collect_vec(), &*alloc);
+    histogram.observe_all(&[1u64, 2, 2000]);
+    histogram.reconfigure_in_place(vec![1, 10]).unwrap();
+    assert_eq!(alloc.allocated.load(Ordering::Relaxed), 6);
+    assert_eq!(alloc.deallocated.load(Ordering::Relaxed), 4);
+    crate::assert_histogram(
+        &histogram,
+        &[(1.0, 1), (10.0, 1), (f64::INFINITY, 1)],
+        2003.0,
+    );
+    drop(histogram);
+    assert_eq!(alloc.deallocated.load(Ordering::Relaxed), 6);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn const_bucket_count() {
+    fn check<const N: usize>() {
+        let histogram = Histogram::new([0u64; N]);
+        assert_eq!(Histogram::<[u64; N]>::BUCKET_COUNT, Some(N + 1));
+        assert_eq!(histogram.0.bucket_count, N + 1);
+        let histogram = Histogram::new([0.0; N]);
+        assert_eq!(Histogram::<[f64; N]>::BUCKET_COUNT, Some(N + 2));
+        assert_eq!(histogram.0.bucket_count, N + 2);
+    }
+    check::<0>();
+    check::<1>();
+    check::<6>();
+    check::<42>();
+    assert_eq!(Histogram::<Vec<f64>>::BUCKET_COUNT, None);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn collect_from() {
+    let samples = vec![0.1, 0.4, 0.7, 1.2, 3.5, 0.2];
+    let snapshot = Histogram::collect_from(vec![0.5, 1.0], samples.clone());
+    assert_eq!(snapshot.count, 6);
+    assert_eq!(
+        snapshot.buckets,
+        vec![(0.5, 3), (1.0, 1), (f64::INFINITY, 2)]
+    );
+    let histogram = Histogram::new(vec![0.5, 1.0]);
+    histogram.observe_all(&samples);
+    assert_eq!(histogram.snapshot().buckets, snapshot.buckets);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn observe_signed_zero() {
+    for zero in [0.0, -0.0] {
+        let histogram = Histogram::new(vec![-1.0, zero, 1.0]);
+        histogram.observe(-0.0);
+        histogram.observe(0.0);
+        let (count, sum, buckets) = histogram.collect();
+        assert_eq!(count, 2);
+        assert_eq!(sum.to_bits(), 0.0f64.to_bits());
+        assert_eq!(
+            buckets.collect_vec(),
+            vec![(-1.0, 0), (0.0, 2), (1.0, 0), (f64::INFINITY, 0)]
+        );
+    }
+}
+
+#[cfg(not(loom))]
+#[test]
+fn capacity_bytes() {
+    let small = Histogram::new(vec![1.0]).capacity_bytes();
+    let large = Histogram::new((0..100).map(f64::from).collect_vec()).capacity_bytes();
+    assert!(small >= 2 * 4 * size_of::<u64>());
+    assert!(large >= 2 * 103 * size_of::<u64>());
+    assert!(large > small);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn prewarm() {
+    let histogram = Histogram::new(vec![1.0]);
+    histogram.prewarm();
+    assert!(histogram.is_empty());
+    histogram.observe(0.5);
+    histogram.prewarm();
+    crate::assert_histogram(&histogram, &[(1.0, 1), (f64::INFINITY, 0)], f64_sum(0.5));
+}
+
+#[cfg(all(feature = "publish", not(loom)))]
+#[test]
+fn publish() {
+    let histogram = Histogram::new(vec![1u64, 2]);
+    assert!(histogram.published().is_none());
+    histogram.observe(1);
+    let snapshot = histogram.publish();
+    histogram.observe(2);
+    let published = histogram.published().unwrap();
+    assert!(std::sync::Arc::ptr_eq(&snapshot, &published));
+    assert_eq!((published.count, published.sum), (1, 1.0));
+    assert_eq!(published.buckets, [(1.0, 1), (2.0, 0), (f64::INFINITY, 0)]);
+    assert_eq!(histogram.publish().count, 2);
+}
+
+#[cfg(all(feature = "publish", loom))]
+#[test]
+fn publish_read_race() {
+    model(|| {
+        let histogram = Histogram::new(vec![1u64]);
+        let h1 = histogram.clone();
+        let h2 = histogram.clone();
+        let t1 = thread::spawn(move || {
+            h1.observe(1);
+            h1.observe(1);
+        });
+        let t2 = thread::spawn(move || {
+            h2.publish();
+        });
+        let mut last_count = 0;
+        for _ in 0..2 {
+            if let Some(snapshot) = histogram.published() {
+                assert_eq!(snapshot.sum, snapshot.count as f64);
+                assert_eq!(snapshot.buckets[0].1, snapshot.count);
+                assert!(snapshot.count >= last_count);
+                last_count = snapshot.count;
+            }
+        }
+        t1.join().unwrap();
+        t2.join().unwrap();
+        assert_eq!(histogram.publish().count, 2);
+    });
+}
+
+#[cfg(not(loom))]
+#[test]
+fn increment_since() {
+    let histogram = Histogram::new(vec![1u64, 2]);
+    histogram.observe_all(&[1, 2, 3]);
+    let baseline = histogram.snapshot();
+    histogram.observe_all(&[1, 1, 3]);
+    let increment = histogram.increment_since(&baseline);
+    assert_eq!((increment.count, increment.sum), (3, 5.0));
+    assert_eq!(increment.buckets, [(1.0, 2), (2.0, 0), (f64::INFINITY, 1)]);
+    // a restarted process starts again from zero
+    let restarted = Histogram::new(vec![1u64, 2]);
+    restarted.observe_all(&[2, 2, 2, 2]);
+    let increment = restarted.increment_since(&baseline);
+    assert_eq!((increment.count, increment.sum), (4, 8.0));
+    assert_eq!(increment.buckets, [(1.0, 0), (2.0, 4), (f64::INFINITY, 0)]);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn cached_boundaries() {
+    use std::time::Duration;
+
+    use crate::HistogramBuckets;
+
+    let buckets = vec![Duration::from_millis(5), Duration::from_secs(1)];
+    let histogram = Histogram::new(buckets.clone());
+    let values = buckets.values().map(|b| b.as_secs_f64()).collect_vec();
+    assert_eq!(*histogram.0.boundaries, values);
+    let (_, _, collected) = histogram.collect();
+    assert_eq!(
+        collected.map(|(b, _)| b).collect_vec(),
+        [0.005, 1.0, f64::INFINITY]
+    );
+}
+
+#[cfg(not(loom))]
+#[test]
+fn small_buckets_index() {
+    use crate::impls::slice_bucket_index;
+
+    let values = [
+        f64::NEG_INFINITY,
+        -1.0,
+        0.0,
+        0.5,
+        1.0,
+        1.5,
+        2.0,
+        2.5,
+        f64::INFINITY,
+        f64::NAN,
+    ];
+    for buckets in [&[][..], &[1.0], &[1.0, 2.0], &[0.0, 1.0, 2.0]] {
+        for value in values {
+            assert_eq!(
+                slice_bucket_index(buckets, &value),
+                buckets.iter().position(|b| value <= *b),
+                "{buckets:?} {value}"
+            );
+        }
+    }
+}
+
+#[cfg(not(loom))]
+#[test]
+fn deep_copy() {
+    let histogram = Histogram::new(vec![1.0, 2.0]);
+    histogram.observe_all(&[1.0, 3.0, f64::NAN]);
+    let copy = histogram.deep_copy();
+    copy.observe(2.0);
+    let (count, _, buckets) = histogram.collect();
+    assert_eq!(count, 3);
+    assert_eq!(
+        buckets.collect_vec(),
+        [(1.0, 1), (2.0, 0), (f64::INFINITY, 1)]
+    );
+    let (count, _, buckets) = copy.collect();
+    assert_eq!(count, 4);
+    assert_eq!(
+        buckets.collect_vec(),
+        [(1.0, 1), (2.0, 1), (f64::INFINITY, 1)]
+    );
+    // counts are copied bucket by bucket, even when boundaries could not be migrated,
+    // e.g. unsorted ones accepted by `new`
+    let histogram = Histogram::new(vec![2u64, 1]);
+    histogram.import(&[0, 1, 0], 1.0, 1);
+    assert_eq!(
+        histogram.deep_copy().collect_counts(),
+        (1, 1.0, vec![0, 1, 0])
+    );
+}
+
+#[cfg(not(loom))]
+#[test]
+fn snapshot_as_map() {
+    let histogram = Histogram::new(vec![1.0, 2.0]);
+    histogram.observe_all(&[0.5, 3.0, 4.0]);
+    let map = histogram.snapshot().as_map();
+    assert_eq!(map.len(), 3);
+    assert_eq!(map[&1.0.into()], 1);
+    assert_eq!(map.last_key_value(), Some((&f64::INFINITY.into(), &2)));
+}
+
+#[cfg(not(loom))]
+#[test]
+fn try_new() {
+    use crate::{HistogramBuckets, HistogramError};
+
+    struct HugeBuckets;
+    impl HistogramBuckets for HugeBuckets {
+        type Value = u64;
+        const LEN: Option<usize> = Some(usize::MAX / 4);
+        fn bucket_index(&self, _value: &Self::Value) -> Option<usize> {
+            None
+        }
+        fn values(&self) -> impl Iterator<Item = Self::Value> {
+            std::iter::empty()
+        }
+    }
+
+    assert_eq!(
+        Histogram::try_new(HugeBuckets).err(),
+        Some(HistogramError::CapacityOverflow)
+    );
+    assert_eq!(
+        Histogram::try_new(vec![2.0, 1.0]).err(),
+        Some(HistogramError::UnsortedBuckets)
+    );
+    assert_eq!(
+        Histogram::try_new(vec![1.0, 1.0]).err(),
+        Some(HistogramError::UnsortedBuckets)
+    );
+    assert_eq!(
+        Histogram::try_new(vec![1.0, f64::NAN]).err(),
+        Some(HistogramError::NanBoundary)
+    );
+    assert_eq!(
+        Histogram::try_new(Vec::<f64>::new()).err(),
+        Some(HistogramError::EmptyBuckets)
+    );
+    assert!(Histogram::try_new(vec![1u64, 2]).is_ok());
+}
+
+#[cfg(all(feature = "sketch", not(loom)))]
+#[test]
+fn sketched_quantiles() {
+    use crate::SketchedHistogram;
+
+    let histogram = SketchedHistogram::new(vec![10.0, 100.0], 0.01, 1e-6, 1e6);
+    assert_eq!(histogram.quantile(0.5), None);
+    let values = (1..=10_000)
+        .map(|i| (i as f64 / 100.0).powi(2))
+        .collect_vec();
+    values.iter().for_each(|v| histogram.observe(*v));
+    for q in [0.0, 0.5, 0.9, 0.99, 0.999, 1.0] {
+        let exact = values[(q * (values.len() - 1) as f64) as usize];
+        let estimated = histogram.quantile(q).unwrap();
+        assert!(
+            (estimated - exact).abs() <= 0.01 * exact,
+            "{q} {exact} {estimated}"
+        );
+    }
+    let (count, _, _) = histogram.collect();
+    assert_eq!(count, 10_000);
+}
+
+#[cfg(all(feature = "sketch", not(loom)))]
+#[test]
+fn sketched_quantiles_nan() {
+    use crate::SketchedHistogram;
+
+    let histogram = SketchedHistogram::new(vec![10.0], 0.01, 1e-6, 1e6);
+    histogram.observe(f64::NAN);
+    assert_eq!(histogram.quantile(0.5), None);
+    histogram.observe(2.0);
+    histogram.observe(f64::NAN);
+    // `NaN` observations are counted, but not ranked
+    assert_eq!(histogram.collect().0, 3);
+    for q in [0.0, 0.5, 1.0] {
+        let estimated = histogram.quantile(q).unwrap();
+        assert!((estimated - 2.0).abs() <= 0.01 * 2.0, "{q} {estimated}");
+    }
+}
+
+#[cfg(not(loom))]
+#[test]
+fn collect_struct() {
+    let histogram = Histogram::new(vec![1u64, 2]);
+    assert_eq!(histogram.collect_struct().mean(), None);
+    histogram.observe_all(&[1, 2, 3, 6]);
+    let collected = histogram.collect_struct();
+    assert_eq!((collected.count, collected.sum), (4, 12.0));
+    assert_eq!(collected.mean(), Some(3.0));
+    let cumulative = collected.cumulative().buckets.collect_vec();
+    assert_eq!(cumulative, [(1.0, 1), (2.0, 2), (f64::INFINITY, 4)]);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn histogram_group() {
+    use crate::HistogramGroup;
+
+    let global = Histogram::new(vec![1.0, 10.0]);
+    let endpoint = Histogram::new(vec![0.5]);
+    let group = HistogramGroup::new([global.clone(), endpoint.clone()]);
+    group.observe(0.7);
+    group.observe_ref(&20.0);
+    let snapshots = group.snapshots();
+    assert_eq!(snapshots.len(), 2);
+    assert_eq!(
+        snapshots[0].buckets,
+        [(1.0, 1), (10.0, 0), (f64::INFINITY, 1)]
+    );
+    assert_eq!(snapshots[1].buckets, [(0.5, 0), (f64::INFINITY, 2)]);
+    assert_eq!(endpoint.collect().0, 2);
+}
+
+#[cfg(not(any(feature = "unsafe", feature = "naive")))]
+#[test]
+fn counters_per_cache_line() {
+    use crate::impls::COUNTERS_PER_CACHE_LINE;
+
+    assert!(COUNTERS_PER_CACHE_LINE.is_power_of_two());
+    #[cfg(all(
+        any(target_arch = "x86_64", target_arch = "aarch64"),
+        not(feature = "cache-line-64")
+    ))]
+    assert_eq!(
+        COUNTERS_PER_CACHE_LINE * align_of::<u64>(),
+        align_of::<crossbeam_utils::CachePadded<()>>()
+    );
+    #[cfg(all(target_pointer_width = "64", feature = "cache-line-64"))]
+    assert_eq!(COUNTERS_PER_CACHE_LINE, 8);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn counters_addressing() {
+    // enough buckets to span several cache lines whatever their size
+    let buckets = (0..40u64).collect_vec();
+    let histogram = Histogram::new(buckets.clone());
+    for value in &buckets {
+        histogram.observe_many(std::iter::repeat_n(*value, *value as usize + 1));
+    }
+    histogram.observe(100);
+    let (_, _, counts) = histogram.read_shard(histogram.hot_shard());
+    let expected = (1..=40).chain([1]).collect_vec();
+    assert_eq!(counts, expected);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn reset() {
+    let histogram = Histogram::new(vec![1.0]);
+    assert_eq!(histogram.reset_epoch(), 0);
+    histogram.observe_all(&[0.5, 2.0, f64::NAN]);
+    let (count, sum, buckets) = histogram.collect_and_reset();
+    assert_eq!(count, 3);
+    assert_f64_sum(sum, f64::NAN);
+    assert_eq!(buckets.collect_vec(), [(1.0, 1), (f64::INFINITY, 1)]);
+    assert_eq!(histogram.reset_epoch(), 1);
+    histogram.observe(1.5);
+    let (count, sum, buckets) = histogram.collect();
+    assert_eq!((count, sum), (1, f64_sum(1.5)));
+    assert_eq!(buckets.collect_vec(), [(1.0, 0), (f64::INFINITY, 1)]);
+    histogram.reset();
+    assert_eq!(histogram.reset_epoch(), 2);
+    let (count, sum, _) = histogram.collect();
+    assert_eq!((count, sum), (0, 0.0));
+
+    let histogram = Histogram::new(vec![-1i64]);
+    histogram.observe_all(&[-5, 3]);
+    histogram.reset();
+    histogram.observe(-2);
+    let (count, sum, _) = histogram.collect();
+    assert_eq!((count, sum), (1, -2.0));
+}
+
+#[cfg(not(loom))]
+#[test]
+fn reconfigure() {
+    let histogram = Histogram::new(vec![1.0, 2.0, 5.0]);
+    histogram.observe_all(&[0.5, 1.5, 2.5, 3.0, 10.0, f64::NAN]);
+    let coarser = histogram.reconfigure(vec![2.0, 10.0]);
+    // raw counts include the NaN bucket
+    let (_, _, counts) = coarser.read_shard(coarser.hot_shard());
+    assert_eq!(counts, [2, 2, 1, 1]);
+    let (count, sum, buckets) = coarser.collect();
+    assert_eq!(count, 6);
+    assert_f64_sum(sum, f64::NAN);
+    assert_eq!(
+        buckets.collect_vec(),
+        [(2.0, 2), (10.0, 2), (f64::INFINITY, 1)]
+    );
+}
+
+#[cfg(not(loom))]
+#[test]
+fn reconfigure_in_place() {
+    let mut histogram = Histogram::new((1..=1000).map(f64::from).collect_vec());
+    histogram.observe_all(&[0.5, 1.5, 2.5, 3.0, 10.0, 2000.0]);
+    histogram.rotate();
+    histogram.observe(f64::NAN);
+    let capacity = histogram.capacity_bytes();
+    let clone = histogram.clone();
+    assert_eq!(
+        histogram.reconfigure_in_place(vec![2.0, 10.0]),
+        Err(vec![2.0, 10.0])
+    );
+    drop(clone);
+    histogram.reconfigure_in_place(vec![2.0, 10.0]).unwrap();
+    assert!(histogram.capacity_bytes() < capacity);
+    let (count, sum, buckets) = histogram.collect();
+    assert_eq!(count, 7);
+    assert_f64_sum(sum, f64::NAN);
+    assert_eq!(
+        buckets.collect_vec(),
+        [(2.0, 2), (10.0, 3), (f64::INFINITY, 1)]
+    );
+    histogram.observe(1.0);
+    crate::assert_histogram(
+        &histogram,
+        &[(2.0, 3), (10.0, 3), (f64::INFINITY, 1)],
+        f64_sum(f64::NAN),
+    );
+    histogram.reset();
+    histogram.observe(1.0);
+    crate::assert_histogram(
+        &histogram,
+        &[(2.0, 1), (10.0, 0), (f64::INFINITY, 0)],
+        f64_sum(1.0),
+    );
+}
+
+#[cfg(not(loom))]
+#[test]
+fn wake_count() {
+    use std::sync::atomic::Ordering;
+
+    use crate::HistogramCounters;
+
+    let histogram = Histogram::new(vec![1u64]);
+    let shard = &histogram.0.shards[histogram.0.hot_shard.load(Ordering::Relaxed)];
+    // in-flight observation, with its bucket incremented but not its count
+    shard.counters.bucket(0).fetch_add(1, Ordering::Relaxed);
+    thread::scope(|s| {
+        let collector = s.spawn(|| histogram.collect().0);
+        #[cfg(not(feature = "separate-waiting-flag"))]
+        let waiting = || {
+            let count = shard.counters.count().load(Ordering::Relaxed);
+            count & crate::Shard::<Vec<u64>>::WAITING_FLAG != 0
+        };
+        #[cfg(feature = "separate-waiting-flag")]
+        let waiting = || shard.waiting.load(Ordering::Relaxed);
+        while !waiting() {
+            thread::yield_now();
+        }
+        shard.import(&[0, 0], 0.0, 1, &histogram.0.waker);
+        assert_eq!(collector.join().unwrap(), 1);
+    });
+    assert_eq!(histogram.wake_count(), 1);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn rotate() {
+    use crate::ShardId;
+
+    let histogram = Histogram::new(vec![1u64]);
+    assert_eq!(histogram.hot_shard(), ShardId::First);
+    assert_eq!(histogram.rotate(), ShardId::Second);
+    assert_eq!(histogram.hot_shard(), ShardId::Second);
+    histogram.observe(1);
+    assert_eq!(histogram.read_shard(ShardId::Second).0, 1);
+    assert_eq!(histogram.rotate(), ShardId::First);
+    assert_eq!(histogram.collect().0, 1);
+}
+
+#[cfg(all(feature = "f64-no-sum", not(loom)))]
+#[test]
+fn f64_no_sum() {
+    let histogram = Histogram::new(vec![1.0, 2.0]);
+    histogram.observe_all(&[0.5, 1.5, 1.7, 3.0, f64::NAN]);
+    let (count, sum, buckets) = histogram.collect();
+    assert_eq!((count, sum), (5, 0.0));
+    assert_eq!(
+        buckets.collect_vec(),
+        [(1.0, 1), (2.0, 2), (f64::INFINITY, 1)]
+    );
+}
+
+#[cfg(not(loom))]
+#[test]
+fn observe_sorted() {
+    use crate::{LogBuckets, NativeBuckets};
+
+    let mut values = vec![
+        -1.0,
+        0.0,
+        0.5,
+        1.0,
+        1.0,
+        1.5,
+        2.0,
+        3.0,
+        100.0,
+        f64::INFINITY,
+    ];
+    let expected = Histogram::new(vec![0.0, 1.0, 2.0]);
+    expected.observe_many(values.clone());
+    let sorted = Histogram::new(vec![0.0, 1.0, 2.0]);
+    sorted.observe_sorted(values.clone().into_iter().chain([f64::NAN]));
+    assert_eq!(sorted.snapshot().buckets, expected.snapshot().buckets);
+    assert_eq!(sorted.collect().0, values.len() as u64 + 1);
+
+    values.retain(|v| *v > 0.0);
+    let expected = Histogram::new(LogBuckets::new(0.5, 2.0, 4));
+    expected.observe_many(values.clone());
+    let sorted = Histogram::new(LogBuckets::new(0.5, 2.0, 4));
+    sorted.observe_sorted(values.clone());
+    assert_eq!(sorted.snapshot().buckets, expected.snapshot().buckets);
+    let expected = Histogram::new(NativeBuckets::new(1, -2, 6));
+    expected.observe_many(values.clone());
+    let sorted = Histogram::new(NativeBuckets::new(1, -2, 6));
+    sorted.observe_sorted(values);
+    assert_eq!(sorted.snapshot().buckets, expected.snapshot().buckets);
+}
+
+#[cfg(all(debug_assertions, not(loom)))]
+#[test]
+#[should_panic(expected = "values must be sorted")]
+fn observe_sorted_unsorted() {
+    let histogram = Histogram::new(vec![1u64, 2]);
+    histogram.observe_sorted([2, 1]);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn overflow_count() {
+    let histogram = Histogram::new(vec![1.0, 2.0]);
+    histogram.observe_all(&[0.5, 3.0, f64::NAN, f64::INFINITY]);
+    assert_eq!(histogram.overflow_count(), 2);
+    let _ = histogram.collect();
+    histogram.observe(2.5);
+    assert_eq!(histogram.overflow_count(), 3);
+    let histogram = Histogram::new(vec![1u64]);
+    histogram.observe_all(&[1, 2, u64::MAX]);
+    assert_eq!(histogram.overflow_count(), 2);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn collect_full() {
+    let histogram = Histogram::new(vec![1u64, 2, 3]);
+    histogram.observe_all(&[1, 1, 3, 5, 5, 5]);
+    let collected = histogram.collect_full();
+    assert_eq!((collected.count, collected.sum), (6, 20.0));
+    assert_eq!(
+        collected.raw_buckets,
+        [(1.0, 2), (2.0, 0), (3.0, 1), (f64::INFINITY, 3)]
+    );
+    let running_sum = (collected.raw_buckets.iter())
+        .scan(0, |sum, (b, c)| {
+            *sum += c;
+            Some((*b, *sum))
+        })
+        .collect_vec();
+    assert_eq!(collected.cumulative_buckets, running_sum);
+    assert_eq!(
+        collected.cumulative_buckets.last().unwrap().1,
+        collected.count
+    );
+    assert_eq!(collected.nan_count, 0);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn collect_full_nan_count() {
+    let histogram = Histogram::new(vec![1.0]);
+    histogram.observe_all(&[0.5, f64::NAN, 2.0]);
+    histogram.rotate();
+    histogram.observe(f64::NAN);
+    let collected = histogram.collect_full();
+    assert_eq!(collected.count, 4);
+    assert_eq!(collected.nan_count, 2);
+    assert_eq!(collected.raw_buckets, [(1.0, 1), (f64::INFINITY, 1)]);
+    assert_eq!(collected.cumulative_buckets, [(1.0, 1), (f64::INFINITY, 2)]);
+}
+
+#[cfg(loom)]
+#[test]
+fn concurrent_reset() {
+    fn check((count, sum, buckets): (u64, f64, impl Iterator<Item = (f64, u64)>)) -> u64 {
+        assert_eq!(sum, count as f64);
+        assert_eq!(buckets.collect_vec(), [(1.0, count), (f64::INFINITY, 0)]);
+        count
+    }
+    model(|| {
+        let histogram = Histogram::new(vec![1u64]);
+        let h1 = histogram.clone();
+        let t1 = thread::spawn(move || h1.observe(1));
+        let reset = check(histogram.collect_and_reset());
+        t1.join().unwrap();
+        assert_eq!(reset + check(histogram.collect()), 1);
+    });
+    // f64 sum is zeroed with a CAS, contrary to integer sums
+    model(|| {
+        let histogram = Histogram::new(vec![1.0]);
+        let h1 = histogram.clone();
+        let t1 = thread::spawn(move || h1.observe(1.0));
+        let reset = check(histogram.collect_and_reset());
+        t1.join().unwrap();
+        assert_eq!(reset + check(histogram.collect()), 1);
+    });
+}
+
+#[cfg(not(loom))]
+#[test]
+fn snapshot_bytes() {
+    use crate::{DecodeError, HistogramSnapshot};
+
+    let histogram = Histogram::new(vec![1.0, 2.0]);
+    histogram.observe_all(&[0.5, 3.0, f64::NAN]);
+    let snapshot = histogram.snapshot();
+    let bytes = snapshot.to_bytes();
+    assert_eq!(bytes.len(), 1 + 4 + 3 * 16 + 16);
+    let decoded = HistogramSnapshot::from_bytes(&bytes).unwrap();
+    assert_eq!(decoded.count, snapshot.count);
+    assert_f64_sum(decoded.sum, f64::NAN);
+    assert_eq!(decoded.buckets, snapshot.buckets);
+    assert_eq!(decoded.buckets.last().unwrap().0, f64::INFINITY);
+
+    let from_bytes = |bytes: &[u8]| HistogramSnapshot::from_bytes(bytes).err();
+    assert_eq!(from_bytes(&[]), Some(DecodeError::Truncated));
+    assert_eq!(
+        from_bytes(&bytes[..bytes.len() - 1]),
+        Some(DecodeError::Truncated)
+    );
+    assert_eq!(
+        from_bytes(&[bytes.as_slice(), &[0]].concat()),
+        Some(DecodeError::TrailingBytes)
+    );
+    assert_eq!(from_bytes(&[2]), Some(DecodeError::UnsupportedVersion(2)));
+    let mut invalid = bytes.clone();
+    invalid[bytes.len() - 8] = 1;
+    assert_eq!(from_bytes(&invalid), Some(DecodeError::InvalidCount));
+    // huge bucket count must not be allocated
+    assert_eq!(
+        from_bytes(&[1, 255, 255, 255, 255]),
+        Some(DecodeError::Truncated)
+    );
+}
+
+#[cfg(not(loom))]
+#[test]
+fn range_buckets() {
+    let histogram = Histogram::new(100..103u64);
+    histogram.observe_all(&[42u64, 100, 101, 101, 102, 103, 600]);
+    crate::assert_histogram(
+        &histogram,
+        &[(100.0, 2), (101.0, 2), (102.0, 1), (f64::INFINITY, 2)],
+        1149.0,
+    );
+    let histogram = Histogram::new(100..=102u64);
+    histogram.observe_all(&[42u64, 100, 101, 101, 102, 103, 600]);
+    crate::assert_histogram(
+        &histogram,
+        &[(100.0, 2), (101.0, 2), (102.0, 1), (f64::INFINITY, 2)],
+        1149.0,
+    );
+}
+
+#[cfg(not(loom))]
+#[test]
+fn histogram_macro() {
+    use crate::{HistogramBuckets, LogBuckets};
+
+    fn boundaries<B: HistogramBuckets>(histogram: &Histogram<B>) -> Vec<f64> {
+        histogram.collect().2.map(|(b, _)| b).collect()
+    }
+    assert_eq!(
+        boundaries(&crate::histogram!(linear: 0.0, 1.5, 4)),
+        boundaries(&Histogram::new(vec![0.0, 1.5, 3.0, 4.5]))
+    );
+    assert_eq!(
+        boundaries(&crate::histogram!(exponential: 1.0, 2.0, 8)),
+        boundaries(&Histogram::new(LogBuckets::new(1.0, 2.0, 8)))
+    );
+    assert_eq!(
+        boundaries(&crate::histogram!(buckets: [0.1, 0.5, 1.0])),
+        boundaries(&Histogram::new(vec![0.1, 0.5, 1.0]))
+    );
+}
+
+#[cfg(not(loom))]
+#[test]
+fn observe_opt() {
+    let histogram = Histogram::new(vec![1.0]);
+    histogram.observe_opt(None);
+    crate::assert_histogram(&histogram, &[(1.0, 0), (f64::INFINITY, 0)], 0.0);
+    histogram.observe_opt(Some(2.0));
+    histogram.observe_opt(None);
+    assert_eq!(histogram.collect().0, 1);
+    crate::assert_histogram(&histogram, &[(1.0, 0), (f64::INFINITY, 1)], f64_sum(2.0));
+}
+
+#[cfg(not(loom))]
+#[test]
+#[should_panic(expected = "bucket index 42 out of range")]
+fn invalid_bucket_index() {
+    use crate::HistogramBuckets;
+
+    struct InvalidBuckets;
+    impl HistogramBuckets for InvalidBuckets {
+        type Value = u64;
+        fn bucket_index(&self, _value: &Self::Value) -> Option<usize> {
+            Some(42)
+        }
+        fn values(&self) -> impl Iterator<Item = Self::Value> {
+            [1].into_iter()
+        }
+    }
+
+    Histogram::new(InvalidBuckets).observe(0);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn shard_counts() {
+    let histogram = Histogram::new(vec![1.0]);
+    histogram.observe_all(&[0.5; 3]);
+    assert_eq!(histogram.shard_counts(), [3, 0]);
+    let _ = histogram.collect();
+    histogram.observe_all(&[0.5; 5]);
+    assert_eq!(histogram.shard_counts(), [3, 5]);
+    let _ = histogram.collect();
+    histogram.observe(0.5);
+    assert_eq!(histogram.shard_counts(), [4, 5]);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn is_empty() {
+    let histogram = Histogram::new(vec![1.0]);
+    assert!(histogram.is_empty());
+    histogram.observe(0.5);
+    assert!(!histogram.is_empty());
+    // collected observations stay in the cold shard
+    let _ = histogram.collect();
+    assert!(!histogram.is_empty());
+    histogram.reset();
+    assert!(histogram.is_empty());
+}
+
+#[cfg(not(loom))]
+#[test]
+fn collect_timeout() {
+    use std::{sync::atomic::Ordering, time::Duration};
+
+    use crate::{HistogramCounters, TimeoutError};
+
+    let histogram = Histogram::new(vec![1.0]);
+    histogram.observe(0.5);
+    let (count, ..) = histogram.collect_timeout(Duration::ZERO).unwrap();
+    assert_eq!(count, 1);
+    // stall an observation between its bucket and count increments
+    let counters = &histogram.0.shards[histogram.0.hot_shard.load(Ordering::Relaxed)].counters;
+    counters.bucket(0).fetch_add(1, Ordering::Relaxed);
+    let timeout = Duration::from_millis(10);
+    assert_eq!(histogram.collect_timeout(timeout).err(), Some(TimeoutError));
+    // the waiting flag has been cleared
+    assert_eq!(counters.count().load(Ordering::Relaxed), 0);
+    thread::scope(|s| {
+        s.spawn(|| {
+            thread::sleep(timeout);
+            counters.count().fetch_add(1, Ordering::Release);
+            histogram.0.waker.wake_collector();
+        });
+        let (count, _, buckets) = histogram.collect_timeout(Duration::from_secs(10)).unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(buckets.collect_vec(), [(1.0, 2), (f64::INFINITY, 0)]);
+    });
+}
+
+#[cfg(not(loom))]
+#[test]
+fn parse_prometheus_text() {
+    use crate::{HistogramSnapshot, ParseError};
+
+    let histogram = Histogram::new(vec![0.005, 1.0]);
+    histogram.observe_all(&[0.001, 2.0, 0.5, f64::NAN]);
+    let mut text = String::new();
+    histogram.write_text(&mut text, "latency").unwrap();
+    let parsed = HistogramSnapshot::parse_prometheus_text(&text, "latency").unwrap();
+    let snapshot = histogram.snapshot();
+    assert_eq!(parsed.count, snapshot.count);
+    assert_f64_sum(parsed.sum, f64::NAN);
+    assert_eq!(parsed.buckets, snapshot.buckets);
+
+    let text = "# HELP other_count unrelated
+other_count 42
+latency_count 3
+latency_bucket{job=\"a b\",le=\"+Inf\"} 3 1700000000
+latency_sum 4.5
+latency_bucket{job=\"a b\",le=\"1.0\"} 1
+latency_bucket{job=\"a b\",le=\"2.0\"} 2
+";
+    let parsed = HistogramSnapshot::parse_prometheus_text(text, "latency").unwrap();
+    assert_eq!((parsed.count, parsed.sum), (3, 4.5));
+    assert_eq!(parsed.buckets, [(1.0, 1), (2.0, 1), (f64::INFINITY, 1)]);
+
+    let parse = |text| HistogramSnapshot::parse_prometheus_text(text, "h").err();
+    assert_eq!(parse("h_sum 1\nh_count 1"), Some(ParseError::MissingSeries));
+    assert_eq!(parse("h_sum one"), Some(ParseError::InvalidLine(1)));
+    assert_eq!(
+        parse("h_bucket{le=\"1\"} 2\nh_bucket{le=\"+Inf\"} 1\nh_sum 1\nh_count 2"),
+        Some(ParseError::NotCumulative)
+    );
+}
+
+#[cfg(not(loom))]
+#[test]
+fn enum_buckets() {
+    use crate::{Categorical, Category, EnumBuckets};
+
+    #[derive(Debug, Clone, Copy)]
+    enum Method {
+        Get,
+        Post,
+        Delete,
+    }
+    impl Categorical for Method {
+        const VARIANTS: &'static [Self] = &[Self::Get, Self::Post, Self::Delete];
+        fn index(&self) -> usize {
+            *self as usize
+        }
+    }
+
+    let histogram = Histogram::new(EnumBuckets::<Method>::new());
+    let methods = [Method::Get, Method::Delete, Method::Get, Method::Post];
+    histogram.observe_many(methods.map(Category));
+    crate::assert_histogram(
+        &histogram,
+        &[(0.0, 2), (1.0, 1), (2.0, 1), (f64::INFINITY, 0)],
+        0.0,
+    );
+    assert_eq!(histogram.collect().0, 4);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn overflow_policy() {
+    use crate::OverflowPolicy;
+
+    for (policy, expected, sum) in [
+        (
+            OverflowPolicy::CountInf,
+            [(1.0, 1), (2.0, 0), (f64::INFINITY, 2)],
+            7.5,
+        ),
+        (
+            OverflowPolicy::Drop,
+            [(1.0, 1), (2.0, 0), (f64::INFINITY, 0)],
+            0.5,
+        ),
+        (
+            OverflowPolicy::Saturate,
+            [(1.0, 1), (2.0, 2), (f64::INFINITY, 0)],
+            7.5,
+        ),
+    ] {
+        let histogram = Histogram::with_overflow_policy(vec![1.0, 2.0], policy);
+        histogram.observe_all(&[0.5, 3.0]);
+        histogram.observe_sorted([4.0]);
+        crate::assert_histogram(&histogram, &expected, f64_sum(sum));
+        histogram.observe(f64::NAN);
+        assert_eq!(
+            histogram.collect().0,
+            expected.iter().map(|(_, c)| c).sum::<u64>() + 1
+        );
+    }
+}
+
+#[cfg(not(loom))]
+#[test]
+fn collect_cumulative_iter() {
+    let histogram = Histogram::new(vec![1.0, 2.0]);
+    histogram.observe_all(&[0.5, 3.0, 1.5, 0.5]);
+    let cumulative = histogram.collect_cumulative_iter().collect_vec();
+    assert_eq!(cumulative, [(1.0, 2), (2.0, 3), (f64::INFINITY, 4)]);
+    assert_eq!(cumulative.last().unwrap().1, histogram.collect().0);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn offset_buckets() {
+    use crate::{HistogramBuckets, OffsetBuckets};
+
+    let buckets = OffsetBuckets::new(vec![0.0, 1.0], 10.0);
+    assert_eq!(buckets.values().collect_vec(), [10.0, 11.0]);
+    let histogram = Histogram::new(buckets);
+    histogram.observe_all(&[-5.0, 5.0, 10.0, 10.5, 11.0, 12.0, f64::NAN]);
+    crate::assert_histogram(
+        &histogram,
+        &[(10.0, 3), (11.0, 2), (f64::INFINITY, 1)],
+        f64_sum(f64::NAN),
+    );
+    // unsigned values below the offset do not underflow
+    let histogram = Histogram::new(OffsetBuckets::new([0u64, 1], 10));
+    histogram.observe_all(&[5u64, 10, 11, 12]);
+    crate::assert_histogram(
+        &histogram,
+        &[(10.0, 2), (11.0, 1), (f64::INFINITY, 1)],
+        38.0,
+    );
+}
+
+#[cfg(not(loom))]
+#[test]
+fn collect_with_diag() {
+    use std::sync::atomic::Ordering;
+
+    use crate::{HistogramCounters, ShardDiag};
+
+    let histogram = Histogram::new(vec![1.0]);
+    histogram.observe(0.5);
+    let (collected, diags) = histogram.collect_with_diag();
+    assert_eq!(collected.count, 1);
+    assert_eq!(
+        diags,
+        [ShardDiag {
+            spins: 1,
+            cold: false
+        }; 2]
+    );
+    // stall an observation in the second shard, completed once the collector waits
+    let shard = &histogram.0.shards[1];
+    shard.counters.bucket(0).fetch_add(1, Ordering::Relaxed);
+    thread::scope(|s| {
+        let collector = s.spawn(|| histogram.collect_with_diag());
+        #[cfg(not(feature = "separate-waiting-flag"))]
+        let waiting = || {
+            let count = shard.counters.count().load(Ordering::Relaxed);
+            count & crate::Shard::<Vec<f64>>::WAITING_FLAG != 0
+        };
+        #[cfg(feature = "separate-waiting-flag")]
+        let waiting = || shard.waiting.load(Ordering::Relaxed);
+        while !waiting() {
+            thread::yield_now();
+        }
+        shard.import(&[], 0.0, 1, &histogram.0.waker);
+        let (collected, [first, second]) = collector.join().unwrap();
+        assert_eq!(collected.count, 2);
+        assert_eq!(
+            first,
+            ShardDiag {
+                spins: 1,
+                cold: false
+            }
+        );
+        assert!(second.cold);
+    });
+}
+
+#[cfg(not(loom))]
+#[test]
+fn bool_values() {
+    let histogram = Histogram::new([false, true]);
+    histogram.observe_all(&[true, false, true, true]);
+    crate::assert_histogram(&histogram, &[(0.0, 1), (1.0, 3), (f64::INFINITY, 0)], 3.0);
+    let copy = histogram.deep_copy();
+    crate::assert_histogram(&copy, &[(0.0, 1), (1.0, 3), (f64::INFINITY, 0)], 3.0);
+}
+
+#[cfg(all(not(loom), feature = "unsafe"))]
+#[test]
+fn observe_unchecked() {
+    use crate::HistogramBuckets;
+
+    let buckets = vec![1.0, 2.0];
+    let checked = Histogram::new(buckets.clone());
+    let unchecked = Histogram::new(buckets.clone());
+    for value in [0.5, 1.5, 1.5, 3.0] {
+        checked.observe(value);
+        let bucket_index = buckets.bucket_index(&value).unwrap_or(buckets.len());
+        // SAFETY: the index is either a bucket index or the `+Inf` bucket one
+        unsafe { unchecked.observe_unchecked(bucket_index, value) };
+    }
+    assert_eq!(checked.snapshot().buckets, unchecked.snapshot().buckets);
+    crate::assert_histogram(
+        &unchecked,
+        &[(1.0, 1), (2.0, 2), (f64::INFINITY, 1)],
+        f64_sum(6.5),
+    );
+}
+
+#[cfg(not(loom))]
+#[test]
+fn observe_many_weighted() {
+    let pairs = [(0.5, 3), (1.5, 0), (3.0, 2), (0.5, 1), (f64::NAN, 1)];
+    let weighted = Histogram::new(vec![1.0, 2.0]);
+    weighted.observe_many_weighted(pairs);
+    let repeated = Histogram::new(vec![1.0, 2.0]);
+    for (value, weight) in pairs {
+        repeated.observe_many(std::iter::repeat_n(value, weight as usize));
+    }
+    let (weighted, repeated) = (weighted.snapshot(), repeated.snapshot());
+    assert_eq!(weighted.count, repeated.count);
+    assert_eq!(weighted.buckets, repeated.buckets);
+    assert_f64_sum(weighted.sum, f64::NAN);
+    assert_f64_sum(repeated.sum, f64::NAN);
+
+    let histogram = Histogram::new(vec![10u64]);
+    histogram.observe_many_weighted([(1u64, 4), (20, 2)]);
+    crate::assert_histogram(&histogram, &[(10.0, 4), (f64::INFINITY, 2)], 44.0);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn observe_many_weighted_exact_sum() {
+    use std::time::Duration;
+
+    use crate::{Cents, HistogramBuckets};
+
+    // raw sums, before their conversion to `f64`
+    fn check<B: HistogramBuckets + Clone>(buckets: B, pairs: &[(B::Value, u64)], expected: u128)
+    where
+        B::Value: Clone,
+    {
+        let weighted = Histogram::new(buckets.clone());
+        weighted.observe_many_weighted(pairs.iter().cloned());
+        let repeated = Histogram::new(buckets);
+        for (value, weight) in pairs {
+            repeated.observe_many(std::iter::repeat_n(value.clone(), *weight as usize));
+        }
+        let raw_sum = |h: &Histogram<B>| h.0.shards.each_ref().map(|shard| shard.load_sum());
+        assert_eq!(raw_sum(&weighted), [expected, 0]);
+        assert_eq!(raw_sum(&repeated), [expected, 0]);
+    }
+
+    let half = u64::MAX / 2;
+    check(vec![1u64], &[(half, 1), (3, 5)], u128::from(half) + 15);
+    check(
+        vec![1i64],
+        &[(-3, 4), (i64::MAX, 1)],
+        (i64::MAX - 12) as u128,
+    );
+    let large = (1u128 << 100) + 1;
+    check(vec![1u128], &[(large, 3)], 3 * large);
+    check(vec![Cents(1)], &[(Cents(-7), 9)], (-63i64) as u64 as u128);
+    let nanos = Duration::from_nanos(u64::from(u32::MAX) + 2);
+    check(
+        vec![nanos],
+        &[(nanos, 3), (Duration::ZERO, 2)],
+        3 * nanos.as_nanos(),
+    );
+}
+
+#[cfg(all(not(loom), feature = "tracing"))]
+#[test]
+#[tracing_test::traced_test]
+fn trace_collect() {
+    let histogram = Histogram::new(vec![1.0]);
+    histogram.observe_all(&[0.5, 2.0, 0.5]);
+    histogram.trace_collect("latency");
+    assert!(logs_contain("histogram collected"));
+    assert!(logs_contain("name=\"latency\""));
+    assert!(logs_contain("count=3"));
+    #[cfg(not(feature = "f64-no-sum"))]
+    assert!(logs_contain("sum=3.0"));
+    assert!(logs_contain("buckets=[(1.0, 2), (inf, 1)]"));
+}
+
+#[cfg(all(not(loom), feature = "smallvec"))]
+#[test]
+fn smallvec_buckets() {
+    use smallvec::{smallvec, SmallVec};
+
+    // inline capacity larger than the bucket count
+    let buckets: SmallVec<[f64; 4]> = smallvec![1.0, 2.0];
+    let histogram = Histogram::new(buckets);
+    histogram.observe_all(&[0.5, 1.5, 3.0]);
+    crate::assert_histogram(
+        &histogram,
+        &[(1.0, 1), (2.0, 1), (f64::INFINITY, 1)],
+        f64_sum(5.0),
+    );
+}
+
+#[cfg(all(not(loom), feature = "raw-atomics"))]
+#[test]
+fn raw_atomics() {
+    use crate::ShardId;
+
+    let histogram = Histogram::new(vec![1.0]);
+    histogram.observe_all(&[0.5, 2.0]);
+    let _ = histogram.collect();
+    histogram.observe(0.5);
+    let raw = histogram.raw_atomics();
+    assert_eq!(raw.bucket_count(), 3);
+    let shards = [ShardId::First, ShardId::Second];
+    let count: u64 = shards.iter().map(|shard| raw.count(*shard)).sum();
+    let sum: f64 = shards.iter().map(|shard| raw.sum(*shard)).sum();
+    let buckets = (0..raw.bucket_count())
+        .map(|i| {
+            shards
+                .iter()
+                .map(|shard| raw.bucket(*shard, i))
+                .sum::<u64>()
+        })
+        .collect_vec();
+    let (collected_count, collected_sum, collected_buckets) = histogram.collect();
+    assert_eq!((count, sum), (collected_count, collected_sum));
+    // the `NaN` bucket is not collected
+    assert_eq!(
+        buckets,
+        collected_buckets.map(|(_, c)| c).chain([0]).collect_vec()
+    );
+}
+
+#[cfg(not(loom))]
+#[test]
+fn cents() {
+    use crate::Cents;
+
+    let histogram = Histogram::new(vec![Cents(100), Cents(1000)]);
+    let amounts = (0..10_000i64).map(|i| Cents(i % 1500 - 10)).collect_vec();
+    histogram.observe_all(&amounts);
+    let exact = amounts.iter().map(|c| c.0).sum::<i64>();
+    let (count, sum, buckets) = histogram.collect();
+    assert_eq!(count, 10_000);
+    assert_eq!(sum, exact as f64 / 100.0);
+    assert_eq!(
+        buckets.collect_vec(),
+        [(1.0, 777), (10.0, 6289), (f64::INFINITY, 2934)]
+    );
+    // 0.1 is not exactly representable, so a float sum would drift
+    let histogram = Histogram::new(vec![Cents(100)]);
+    histogram.observe_many(std::iter::repeat_n(Cents(10), 1000));
+    assert_eq!(histogram.collect().1, 100.0);
+    assert_ne!(std::iter::repeat_n(0.1, 1000).sum::<f64>(), 100.0);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn into_f64_not_in_hot_path() {
+    use std::cell::Cell;
+
+    use crate::{AtomicCounter, Cents, HistogramValue, Ordering};
+
+    thread_local! {
+        static CONVERSIONS: Cell<usize> = const { Cell::new(0) };
+    }
+    // fixed-point value with an expensive conversion, counting its calls
+    #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+    struct Counted(Cents);
+    impl HistogramValue for Counted {
+        const HAS_NAN: bool = false;
+        fn into_f64(self) -> f64 {
+            CONVERSIONS.set(CONVERSIONS.get() + 1);
+            self.0.into_f64()
+        }
+        fn is_nan(&self) -> bool {
+            false
+        }
+        fn atomic_add(counter: &AtomicCounter, value: Self, ordering: Ordering) {
+            Cents::atomic_add(counter, value.0, ordering);
+        }
+        fn atomic_add_ref(counter: &AtomicCounter, value: &Self, ordering: Ordering) {
+            Self::atomic_add(counter, *value, ordering);
+        }
+        fn from_bits(bits: u64) -> Self {
+            Self(Cents::from_bits(bits))
+        }
+        fn from_f64(value: f64) -> Self {
+            Self(Cents::from_f64(value))
+        }
+    }
+
+    let histogram = Histogram::new(vec![Counted(Cents(100))]);
+    CONVERSIONS.set(0);
+    for i in 0..1000 {
+        histogram.observe(Counted(Cents(i)));
+    }
+    histogram.observe_sorted((0..1000).map(|i| Counted(Cents(i))));
+    histogram.observe_many((0..1000).map(|i| Counted(Cents(i))));
+    assert_eq!(CONVERSIONS.get(), 0);
+    // only the sum of each shard is converted, boundaries being converted once at creation
+    let (count, sum, buckets) = histogram.collect();
+    assert_eq!(CONVERSIONS.get(), 2);
+    assert_eq!((count, sum), (3000, 3.0 * 4995.0));
+    assert_eq!(buckets.collect_vec(), [(1.0, 303), (f64::INFINITY, 2697)]);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn collect_counts() {
+    let boundaries = [1.0, 2.0];
+    let histogram = Histogram::new(boundaries);
+    histogram.observe_all(&[0.5, 3.0, 1.5, 0.5, f64::NAN]);
+    let (count, sum, counts) = histogram.collect_counts();
+    assert_eq!(count, 5);
+    assert_f64_sum(sum, f64::NAN);
+    assert_eq!(counts.len(), boundaries.len() + 1);
+    assert_eq!(counts, [2, 1, 1]);
+    let (_, _, buckets) = histogram.collect();
+    assert_eq!(buckets.map(|(_, c)| c).collect_vec(), counts);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn collect_large() {
+    // above the parallel collection threshold of `rayon` feature
+    let bucket_count = (1u64 << 14) + 1;
+    let histogram = Histogram::new((0..bucket_count).collect_vec());
+    histogram.observe_many(0..bucket_count);
+    let _ = histogram.collect();
+    histogram.observe_many((0..bucket_count).step_by(2));
+    let (count, sum, buckets) = histogram.collect();
+    assert_eq!(count, bucket_count + bucket_count.div_ceil(2));
+    let expected_sum = (0..bucket_count)
+        .chain((0..bucket_count).step_by(2))
+        .sum::<u64>();
+    assert_eq!(sum, expected_sum as f64);
+    for (i, (boundary, count)) in buckets.enumerate() {
+        let expected = match i as u64 {
+            i if i == bucket_count => 0,
+            i => 1 + (i % 2 == 0) as u64,
+        };
+        assert_eq!((i, count), (i, expected), "boundary {boundary}");
+    }
+}
+
+#[cfg(not(loom))]
+#[test]
+fn max_buckets() {
+    use crate::{HistogramBuckets, HistogramError, MAX_BUCKETS};
+
+    struct LargeBuckets;
+    impl HistogramBuckets for LargeBuckets {
+        type Value = u64;
+        const LEN: Option<usize> = Some(MAX_BUCKETS + 1);
+        fn bucket_index(&self, _value: &Self::Value) -> Option<usize> {
+            None
+        }
+        fn values(&self) -> impl Iterator<Item = Self::Value> {
+            0..=MAX_BUCKETS as u64
+        }
+    }
+
+    assert_eq!(
+        Histogram::try_new(LargeBuckets).err(),
+        Some(HistogramError::TooManyBuckets)
+    );
+    let histogram = Histogram::with_max_buckets(vec![1.0, 2.0], 2);
+    histogram.observe(1.5);
+    assert_eq!(histogram.collect().0, 1);
+}
+
+#[cfg(not(loom))]
+#[test]
+#[should_panic(expected = "too many buckets: 3 > 2")]
+fn max_buckets_exceeded() {
+    let _ = Histogram::with_max_buckets(vec![1.0, 2.0, 3.0], 2);
+}
+
+#[cfg(all(debug_assertions, not(loom)))]
+#[test]
+#[should_panic(
+    expected = "`HistogramBuckets::LEN` must be the count of `HistogramBuckets::values`"
+)]
+fn inconsistent_len() {
+    use crate::HistogramBuckets;
+
+    struct ShortBuckets;
+    impl HistogramBuckets for ShortBuckets {
+        type Value = u64;
+        const LEN: Option<usize> = Some(1);
+        fn bucket_index(&self, value: &Self::Value) -> Option<usize> {
+            (*value <= 2).then_some(*value as usize)
+        }
+        fn values(&self) -> impl Iterator<Item = Self::Value> {
+            [0, 1, 2].into_iter()
+        }
+    }
+
+    let _ = Histogram::new(ShortBuckets);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn u128_values() {
+    let large = 1u128 << 100;
+    let histogram = Histogram::new(vec![u128::from(u64::MAX), large]);
+    histogram.observe(u128::from(u64::MAX));
+    histogram.observe(u128::from(u64::MAX));
+    histogram.observe(large + 1);
+    let expected_sum = 2 * u128::from(u64::MAX) + large + 1;
+    let (count, sum, buckets) = histogram.collect();
+    assert_eq!(count, 3);
+    assert_eq!(sum, expected_sum as f64);
+    assert_eq!(
+        buckets.collect_vec(),
+        vec![(u64::MAX as f64, 2), (large as f64, 0), (f64::INFINITY, 1)]
+    );
+    // the carry is subtracted back on reset
+    histogram.observe(1);
+    let (count, sum, _) = histogram.collect_and_reset();
+    assert_eq!((count, sum), (4, (expected_sum + 1) as f64));
+    histogram.observe(u128::from(u64::MAX));
+    histogram.observe_many_weighted([(u128::from(u64::MAX), 2)]);
+    let (count, sum, _) = histogram.collect();
+    assert_eq!((count, sum), (3, (3 * u128::from(u64::MAX)) as f64));
+}
+
+#[cfg(not(loom))]
+#[test]
+fn u128_exact_sum() {
+    // raw sum of both shards, as `f64` cannot represent it exactly
+    fn raw_sum(histogram: &Histogram<Vec<u128>>) -> u128 {
+        histogram
+            .0
+            .shards
+            .iter()
+            .map(|shard| shard.load_sum())
+            .sum()
+    }
+
+    let value = (1u128 << 100) + 1;
+    let mut histogram = Histogram::new(vec![1u128]);
+    histogram.observe(value);
+    let _ = histogram.collect();
+    histogram.observe_many_weighted([(value, 2)]);
+    assert_eq!(raw_sum(&histogram), 3 * value);
+    assert_eq!(raw_sum(&histogram.deep_copy()), 3 * value);
+    assert_eq!(raw_sum(&histogram.reconfigure(vec![2, 3])), 3 * value);
+    histogram.reconfigure_in_place(vec![4]).unwrap();
+    assert_eq!(raw_sum(&histogram), 3 * value);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn builder() {
+    use std::{sync::atomic::Ordering, time::Duration};
+
+    use crate::{HistogramCounters, OverflowPolicy};
+
+    let histogram = Histogram::builder(vec![0.5, 1.0])
+        .overflow_policy(OverflowPolicy::Saturate)
+        .track_sum(false)
+        .build();
+    histogram.observe(0.2);
+    histogram.observe(2.0);
+    histogram.observe_many_weighted([(0.7, 2)]);
+    histogram.import(&[1, 0, 0], 0.1, 1);
+    let (count, sum, buckets) = histogram.collect();
+    assert_eq!((count, sum), (5, 0.0));
+    assert_eq!(
+        buckets.collect_vec(),
+        vec![(0.5, 2), (1.0, 3), (f64::INFINITY, 0)]
+    );
+
+    // a stalled observation makes the collection wait without spinning
+    let histogram = Histogram::builder(vec![1u64]).spin_limit(0).build();
+    histogram.observe(1);
+    let counters = &histogram.0.shards[histogram.0.hot_shard.load(Ordering::Relaxed)].counters;
+    counters.bucket(0).fetch_add(1, Ordering::Relaxed);
+    assert!(histogram
+        .collect_timeout(Duration::from_millis(10))
+        .is_err());
+    counters.count().fetch_add(1, Ordering::Relaxed);
+    let (_, diags) = histogram.collect_with_diag();
+    assert_eq!(diags.map(|diag| (diag.spins, diag.cold)), [(0, true); 2]);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn clamp_buckets() {
+    use crate::ClampBuckets;
+
+    let histogram = Histogram::new(ClampBuckets::new(vec![1.0, 10.0]));
+    histogram.observe(0.5);
+    histogram.observe(5.0);
+    histogram.observe(100.0);
+    histogram.observe_many_weighted([(20.0, 2)]);
+    histogram.observe_sorted([-1.0, 50.0]);
+    let (count, sum, buckets) = histogram.collect();
+    assert_eq!((count, sum), (7, f64_sum(47.0)));
+    assert_eq!(
+        buckets.collect_vec(),
+        vec![(1.0, 2), (10.0, 5), (f64::INFINITY, 0)]
+    );
+    // `NaN` is not clamped
+    histogram.observe(f64::NAN);
+    assert_f64_sum(histogram.collect().1, f64::NAN);
+
+    let histogram = Histogram::new(ClampBuckets::new(vec![1u64, 10]));
+    histogram.observe_many([0, 5, 100]);
+    histogram.observe_many_weighted([(20, 2)]);
+    histogram.observe_sorted([0, 50]);
+    let (count, sum, buckets) = histogram.collect();
+    assert_eq!((count, sum), (7, 47.0));
+    assert_eq!(
+        buckets.collect_vec(),
+        vec![(1.0, 2), (10.0, 5), (f64::INFINITY, 0)]
+    );
+}
+
+#[cfg(not(loom))]
+#[test]
+fn snapshot_approx_eq() {
+    use crate::HistogramSnapshot;
+
+    let expected = Histogram::collect_from(vec![1.0], [0.3]);
+    // `0.1 + 0.2 != 0.3`
+    let actual = Histogram::collect_from(vec![1.0], [0.1, 0.2]);
+    let actual = HistogramSnapshot {
+        count: 1,
+        buckets: vec![(1.0, 1), (f64::INFINITY, 0)],
+        ..actual
+    };
+    #[cfg(not(feature = "f64-no-sum"))]
+    assert_ne!(actual, expected);
+    assert!(actual.approx_eq(&expected, 1e-12));
+    #[cfg(not(feature = "f64-no-sum"))]
+    assert!(!actual.approx_eq(&expected, 0.0));
+    let other = Histogram::collect_from(vec![1.0], [1.3]);
+    assert!(!other.approx_eq(&expected, 2.0));
+    let nan = Histogram::collect_from(vec![1.0], [f64::NAN]);
+    assert!(nan.approx_eq(&nan, 1.0));
+    let inf = Histogram::collect_from(vec![1.0], [f64::INFINITY]);
+    assert!(inf.approx_eq(&inf, 1.0));
+}
+
+#[cfg(not(loom))]
+#[test]
+fn merge_compatible() {
+    use crate::{HistogramSnapshot, IncompatibleBoundaries};
+
+    let fine = Histogram::collect_from(vec![1.0, 2.0, 3.0, 4.0], [0.5, 1.5, 2.5, 3.5, 3.5, 5.0]);
+    let coarse = Histogram::collect_from(vec![2.0, 4.0], [1.0, 3.0, 6.0]);
+    let expected = HistogramSnapshot {
+        count: 9,
+        sum: f64_sum(26.5),
+        buckets: vec![(2.0, 3), (4.0, 4), (f64::INFINITY, 2)],
+    };
+    assert_eq!(fine.merge_compatible(&coarse), Ok(expected.clone()));
+    assert_eq!(coarse.merge_compatible(&fine), Ok(expected));
+    assert_eq!(
+        fine.merge_compatible(&fine).unwrap(),
+        Histogram::collect_from(
+            vec![1.0, 2.0, 3.0, 4.0],
+            [0.5, 1.5, 2.5, 3.5, 3.5, 5.0].repeat(2)
+        )
+    );
+    let other = Histogram::collect_from(vec![1.0, 3.0], [1.0]);
+    assert_eq!(coarse.merge_compatible(&other), Err(IncompatibleBoundaries));
+    assert_eq!(other.merge_compatible(&coarse), Err(IncompatibleBoundaries));
+}
+
+#[cfg(not(loom))]
+#[test]
+fn drain() {
+    use crate::HistogramSnapshot;
+
+    let histogram = Histogram::new(vec![1.0, 2.0]);
+    histogram.observe_all(&[0.5, 1.5, 1.5]);
+    let first = histogram.drain();
+    histogram.observe_all(&[2.5]);
+    let second = histogram.drain();
+    assert_eq!(
+        first,
+        HistogramSnapshot {
+            count: 3,
+            sum: f64_sum(3.5),
+            buckets: vec![(1.0, 1), (2.0, 2), (f64::INFINITY, 0)],
+        }
+    );
+    assert_eq!(
+        second,
+        HistogramSnapshot {
+            count: 1,
+            sum: f64_sum(2.5),
+            buckets: vec![(1.0, 0), (2.0, 0), (f64::INFINITY, 1)],
+        }
+    );
+    assert_eq!(histogram.reset_epoch(), 2);
+    assert_eq!(histogram.drain().count, 0);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn buckets_for_quantiles() {
+    use crate::Buckets;
+
+    let boundaries = Buckets::for_quantiles(&[0.5, 0.99], 0.0..1000.0);
+    assert!(boundaries.windows(2).all(|w| w[0] < w[1]));
+    assert_eq!(boundaries.first(), Some(&100.0));
+    assert_eq!(boundaries.last(), Some(&1000.0));
+    assert!(boundaries.contains(&500.0) && boundaries.contains(&990.0));
+    // distance between the boundaries surrounding `value`
+    let spacing_around = |value: f64| {
+        let index = boundaries.partition_point(|b| *b < value);
+        boundaries[index + 1] - boundaries[index - 1]
+    };
+    assert!(spacing_around(500.0) <= 10.0);
+    assert!(spacing_around(990.0) <= 10.0);
+    assert!(spacing_around(250.0) >= 100.0);
+    // `3.0 * 0.1` is not exactly `3.0 * 1.0 / 10.0`
+    let boundaries = Buckets::for_quantiles(&[0.1], 0.0..3.0);
+    assert!(boundaries.windows(2).all(|w| w[1] - w[0] > 0.002));
+    // boundaries around targets at the edges are clamped to the range
+    let boundaries = Buckets::for_quantiles(&[0.0, 1.0], -1.0..1.0);
+    assert!((-1.0..-0.99).contains(&boundaries[0]));
+    assert_eq!(boundaries.last(), Some(&1.0));
+    let histogram = Histogram::try_new(boundaries).unwrap();
+    histogram.observe(0.999);
+    assert_eq!(histogram.collect().0, 1);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn abs_buckets() {
+    use crate::{AbsBuckets, ClampBuckets, HistogramBuckets};
+
+    let histogram = Histogram::new(AbsBuckets(vec![1.0, 10.0]));
+    histogram.observe_all(&[-0.5, 0.5, -5.0, 20.0, -20.0, f64::NAN]);
+    crate::assert_histogram(
+        &histogram,
+        &[(1.0, 2), (10.0, 1), (f64::INFINITY, 2)],
+        f64_sum(f64::NAN),
+    );
+    let histogram = Histogram::new(AbsBuckets(vec![1i64, 10]));
+    histogram.observe_all(&[-1i64, 1, -5, 20, -20]);
+    crate::assert_histogram(&histogram, &[(1.0, 2), (10.0, 1), (f64::INFINITY, 2)], 47.0);
+    histogram.observe_sorted([0i64, -2, 3, -30]);
+    crate::assert_histogram(&histogram, &[(1.0, 3), (10.0, 3), (f64::INFINITY, 3)], 82.0);
+    // the magnitude of `i64::MIN` saturates instead of overflowing
+    let histogram = Histogram::new(AbsBuckets(vec![1i64, 10]));
+    histogram.observe(i64::MIN);
+    assert_eq!(
+        histogram.collect_counts(),
+        (1, i64::MAX as f64, vec![0, 0, 1])
+    );
+    // the magnitude is clamped by inner buckets
+    let buckets = AbsBuckets(ClampBuckets::new(vec![1i64, 10]));
+    assert_eq!(buckets.clamp(&-20), Some(10));
+    assert_eq!(buckets.clamp(&-5), Some(5));
+    assert_eq!(buckets.clamp(&5), None);
+    assert_eq!(buckets.clamp(&i64::MIN), Some(10));
+}
+
+#[cfg(not(loom))]
+#[test]
+fn explicit_range_buckets() {
+    use crate::RangeBuckets;
+
+    let histogram = Histogram::new(RangeBuckets(vec![0.0..1.0, 1.0..2.0, 2.0..4.0]));
+    histogram.observe_all(&[0.0, 0.5, 1.0, 3.9, 4.0, -1.0, f64::NAN]);
+    crate::assert_histogram(
+        &histogram,
+        &[(1.0, 2), (2.0, 1), (4.0, 1), (f64::INFINITY, 2)],
+        f64_sum(f64::NAN),
+    );
+}
+
+#[cfg(not(loom))]
+#[test]
+fn explicit_range_buckets_observe_sorted() {
+    use crate::RangeBuckets;
+
+    let buckets = RangeBuckets(vec![0.0..2.0, 1.5..2.5, 3.0..4.0]);
+    let values = [-1.0, 0.0, 1.0, 2.0, 2.25, 2.5, 2.75, 3.0, 4.0, 5.0];
+    let expected = [(2.0, 2), (2.5, 2), (4.0, 1), (f64::INFINITY, 5)];
+    let histogram = Histogram::new(buckets.clone());
+    histogram.observe_all(&values);
+    crate::assert_histogram(&histogram, &expected, f64_sum(21.5));
+    // gaps and overlaps are looked up, not crossed by the cursor
+    let histogram = Histogram::new(buckets);
+    histogram.observe_sorted(values);
+    crate::assert_histogram(&histogram, &expected, f64_sum(21.5));
+}
+
+#[cfg(not(loom))]
+#[test]
+fn explicit_range_buckets_gaps_and_overlaps() {
+    use crate::{HistogramBuckets, OverflowPolicy, RangeBuckets};
+
+    // `[1.5, 2)` overlaps the first range, `[2.5, 3)` is a gap
+    let buckets = RangeBuckets(vec![0.0..2.0, 1.5..2.5, 3.0..4.0]);
+    assert_eq!(buckets.bucket_index(&1.75), Some(0));
+    assert_eq!(buckets.bucket_index(&2.25), Some(1));
+    assert_eq!(buckets.bucket_index(&2.5), None);
+    assert_eq!(buckets.bucket_index(&2.75), None);
+    assert_eq!(buckets.bucket_index(&3.0), Some(2));
+    let histogram = Histogram::new(buckets);
+    histogram.observe_all(&[1.75, 2.25, 2.75, 3.0]);
+    crate::assert_histogram(
+        &histogram,
+        &[(2.0, 1), (2.5, 1), (4.0, 1), (f64::INFINITY, 1)],
+        f64_sum(9.75),
+    );
+    let histogram = Histogram::builder(RangeBuckets(vec![0.0..1.0, 2.0..3.0]))
+        .overflow_policy(OverflowPolicy::Drop)
+        .build();
+    histogram.observe_all(&[1.5, 2.5]);
+    crate::assert_histogram(
+        &histogram,
+        &[(1.0, 0), (3.0, 1), (f64::INFINITY, 0)],
+        f64_sum(2.5),
+    );
+}
+
+#[cfg(not(loom))]
+#[test]
+fn collect_array() {
+    let histogram = Histogram::new([1.0]);
+    histogram.observe_all(&[0.5, 2.0, f64::NAN]);
+    let (count, sum, buckets) = histogram.collect_array::<2>();
+    assert_eq!(count, 3);
+    assert_f64_sum(sum, f64::NAN);
+    assert_eq!(buckets, [(1.0, 1), (f64::INFINITY, 1)]);
+
+    let histogram = Histogram::new([1u64, 2, 4]);
+    histogram.observe_all(&[1u64, 3]);
+    // across both shards
+    histogram.rotate();
+    histogram.observe_all(&[3u64, 8]);
+    let (count, sum, buckets) = histogram.collect_array::<4>();
+    assert_eq!((count, sum), (4, 15.0));
+    assert_eq!(buckets, [(1.0, 1), (2.0, 0), (4.0, 2), (f64::INFINITY, 1)]);
+    assert_eq!(histogram.collect().2.collect_vec(), buckets);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn collect_totals() {
+    let histogram = Histogram::new(vec![1.0, 2.0]);
+    histogram.observe_many([0.5, 1.5, 3.0, f64::INFINITY, 2.5]);
+    let (count, sum) = histogram.collect_totals();
+    assert_eq!((count, sum), (5, f64_sum(f64::INFINITY)));
+    let histogram = Histogram::new(vec![1u64, 2]);
+    histogram.observe_many([0, 1, 2, 3]);
+    let totals = histogram.collect_totals();
+    histogram.observe_many([4, 5]);
+    assert_eq!(histogram.collect_totals(), (6, 15.0));
+    let (count, sum, _) = histogram.collect();
+    assert_eq!((count, sum), histogram.collect_totals());
+    assert_eq!(totals, (4, 6.0));
+}
+
+#[cfg(all(feature = "separate-waiting-flag", not(loom)))]
+#[test]
+fn count_above_waiting_flag_bit() {
+    use std::sync::atomic::Ordering;
+
+    use crate::HistogramCounters;
+
+    let histogram = Histogram::new(vec![1u64]);
+    let shard = &histogram.0.shards[histogram.0.hot_shard.load(Ordering::Relaxed)];
+    shard.counters.count().store(1 << 63, Ordering::Relaxed);
+    shard.counters.bucket(0).store(1 << 63, Ordering::Relaxed);
+    histogram.observe(1);
+    histogram.observe(2);
+    // the top bit of the count is not mistaken for the waiting flag
+    assert_eq!(histogram.wake_count(), 0);
+    let (count, _, buckets) = histogram.collect();
+    assert_eq!(count, (1 << 63) + 2);
+    assert_eq!(
+        buckets.collect_vec(),
+        vec![(1.0, (1 << 63) + 1), (f64::INFINITY, 1)]
+    );
+}
+
+#[cfg(all(feature = "separate-waiting-flag", not(loom)))]
+#[test]
+fn shard_sum_saturates() {
+    use std::sync::atomic::Ordering;
+
+    use crate::HistogramCounters;
+
+    let histogram = Histogram::new([1u64]);
+    for shard in &histogram.0.shards {
+        shard.counters.count().store(u64::MAX, Ordering::Relaxed);
+        shard.counters.bucket(0).store(u64::MAX, Ordering::Relaxed);
+    }
+    assert_eq!(histogram.collect_totals().0, u64::MAX);
+    assert_eq!(histogram.collect_array::<2>().0, u64::MAX);
+    let (count, _, buckets) = histogram.collect();
+    assert_eq!(count, u64::MAX);
+    assert_eq!(
+        buckets.collect_vec(),
+        vec![(1.0, u64::MAX), (f64::INFINITY, 0)]
+    );
+    assert_eq!(histogram.overflow_count(), 0);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn observe_if() {
+    let histogram = Histogram::new(vec![1u64]);
+    for i in 0..4 {
+        histogram.observe_if(i % 2 == 0, i);
+    }
+    let (count, sum, buckets) = histogram.collect();
+    assert_eq!((count, sum), (2, 2.0));
+    assert_eq!(buckets.collect_vec(), vec![(1.0, 1), (f64::INFINITY, 1)]);
+}
+
+#[cfg(all(feature = "numa", not(loom)))]
+#[test]
+fn numa_histogram() {
+    use crate::{numa::parse_cpu_list, NumaHistogram};
+
+    assert_eq!(
+        parse_cpu_list("0-3,8,10-11\n").collect_vec(),
+        [0, 1, 2, 3, 8, 10, 11]
+    );
+    assert_eq!(parse_cpu_list("\n").count(), 0);
+
+    let histogram = NumaHistogram::with_cpu_nodes(vec![1u64, 10], vec![0, 0, 1, 1]);
+    assert_eq!(histogram.node_count(), 2);
+    histogram.nodes[0].observe_many([0, 5]);
+    histogram.nodes[1].observe_many([7, 100, 1]);
+    histogram.observe(3);
+    let (count, sum, buckets) = histogram.collect();
+    assert_eq!((count, sum), (6, 116.0));
+    assert_eq!(
+        buckets.collect_vec(),
+        vec![(1.0, 2), (10.0, 3), (f64::INFINITY, 1)]
+    );
+
+    let histogram = NumaHistogram::new(vec![1.0]);
+    histogram.observe(f64::NAN);
+    histogram.observe(0.5);
+    assert_eq!(histogram.collect().0, 2);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn non_zero_u64_values() {
+    use std::num::NonZeroU64;
+
+    let values = [1, 5, 10, 42];
+    let histogram = Histogram::new(vec![NonZeroU64::new(5).unwrap()]);
+    histogram.observe_many(values.map(|v| NonZeroU64::new(v).unwrap()));
+    histogram.observe_many_weighted([(NonZeroU64::MIN, 3)]);
+    let expected = Histogram::new(vec![5u64]);
+    expected.observe_many(values);
+    expected.observe_many_weighted([(1, 3)]);
+    let (count, sum, buckets) = histogram.collect();
+    let (expected_count, expected_sum, expected_buckets) = expected.collect();
+    assert_eq!((count, sum), (expected_count, expected_sum));
+    assert_eq!(buckets.collect_vec(), expected_buckets.collect_vec());
+    // an empty sum is not representable by `NonZeroU64`
+    let (count, sum, _) = histogram.collect_and_reset();
+    assert_eq!((count, sum), (7, 61.0));
+    assert_eq!(histogram.collect().1, 0.0);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn min_collect_interval() {
+    use std::{sync::atomic::Ordering, time::Duration};
+
+    let histogram = Histogram::builder([1u64])
+        .min_collect_interval(Duration::from_secs(3600))
+        .build();
+    histogram.observe(1);
+    let (count, _, _, stats) = histogram.collect_with_stats();
+    assert_eq!((count, stats.generation), (1, 1));
+    histogram.observe(2);
+    // the cached collection is returned without swapping shards, by every variant
+    let (count, _, _, stats) = histogram.collect_with_stats();
+    assert_eq!((count, stats.generation), (1, 1));
+    assert_eq!(histogram.collect_counts(), (1, 1.0, vec![1, 0]));
+    assert_eq!(histogram.collect_totals(), (1, 1.0));
+    let buckets = [(1.0, 1), (f64::INFINITY, 0)];
+    assert_eq!(histogram.collect_array::<2>(), (1, 1.0, buckets));
+    let timeout = histogram.collect_timeout(Duration::from_secs(1)).unwrap();
+    assert_eq!(timeout.0, 1);
+    assert_eq!(histogram.collect_with_diag().0.count, 1);
+    assert_eq!(histogram.0.generation.load(Ordering::Relaxed), 1);
+    // a reset clears the cache
+    assert_eq!(histogram.collect_and_reset().0, 2);
+    histogram.observe(3);
+    assert_eq!(histogram.collect().0, 1);
+
+    let histogram = Histogram::builder(vec![1u64])
+        .min_collect_interval(Duration::ZERO)
+        .build();
+    histogram.observe(1);
+    let _ = histogram.collect();
+    histogram.observe(2);
+    assert_eq!(histogram.collect().0, 2);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn rotate_and_read_cold() {
+    let histogram = Histogram::new(vec![1.0, 2.0]);
+    histogram.observe_many([0.5, 1.5]);
+    histogram.rotate();
+    histogram.observe_many([2.5, 1.0, 0.0]);
+    let expected = (5, f64_sum(5.5), vec![3, 1, 1]);
+    let (count_cold, sum_cold, buckets_cold) = histogram.read_cold();
+    histogram.rotate();
+    let (count_hot, sum_hot, buckets_hot) = histogram.read_cold();
+    assert_eq!((count_cold, count_hot), (2, 3));
+    let buckets = std::iter::zip(buckets_cold, buckets_hot)
+        .map(|(cold, hot)| cold + hot)
+        .take(3)
+        .collect_vec();
+    assert_eq!(
+        (count_cold + count_hot, sum_cold + sum_hot, buckets),
+        expected
+    );
+    assert_eq!(histogram.collect_counts(), expected);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn snapshot_eq() {
+    use crate::HistogramSnapshot;
+
+    let histogram = Histogram::new(vec![1.0]);
+    histogram.observe(0.5);
+    let snapshot = histogram.snapshot();
+    assert_eq!(snapshot, snapshot.clone());
+    assert_eq!(snapshot, histogram.snapshot());
+    histogram.observe(2.0);
+    assert_ne!(snapshot, histogram.snapshot());
+    let other_boundaries = HistogramSnapshot {
+        buckets: vec![(2.0, 1), (f64::INFINITY, 0)],
+        ..snapshot.clone()
+    };
+    assert_ne!(snapshot, other_boundaries);
+    let other_sum = HistogramSnapshot {
+        sum: 0.25,
+        ..snapshot.clone()
+    };
+    assert_ne!(snapshot, other_sum);
+    // `NaN` sums are equal
+    histogram.observe(f64::NAN);
+    let snapshot = histogram.snapshot();
+    assert_f64_sum(snapshot.sum, f64::NAN);
+    assert_eq!(snapshot, histogram.snapshot());
+    let not_nan = HistogramSnapshot {
+        sum: 1.0,
+        ..snapshot.clone()
+    };
+    assert_ne!(snapshot, not_nan);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn sampled_histogram() {
+    use crate::{sampled::seed_thread, SampledHistogram};
+
+    seed_thread(42);
+    let histogram = SampledHistogram::new(vec![10u64, 50, 90], 10);
+    let expected = Histogram::new(vec![10u64, 50, 90]);
+    for i in 0..100_000 {
+        histogram.observe(i % 100);
+        expected.observe(i % 100);
+    }
+    let (count, sum, buckets) = histogram.collect();
+    let (expected_count, expected_sum, expected_buckets) = expected.collect();
+    let assert_approx = |value: f64, expected: f64| {
+        assert!(
+            (value / expected - 1.0).abs() < 0.05,
+            "{value} vs {expected}"
+        );
+    };
+    assert_eq!(count % 10, 0);
+    assert_approx(count as f64, expected_count as f64);
+    assert_approx(sum, expected_sum);
+    for ((boundary, count), (_, expected)) in buckets.zip(expected_buckets) {
+        assert_eq!(count % 10, 0, "{boundary}");
+        assert_approx(count as f64, expected as f64);
+    }
+
+    // every observation is recorded with a rate of 1
+    let histogram = SampledHistogram::new(vec![1.0], 1);
+    histogram.observe(0.5);
+    histogram.observe(2.0);
+    assert_eq!(histogram.collect().0, 2);
+}
+
+#[cfg(all(feature = "prometheus-client", not(loom)))]
+#[test]
+fn prometheus_encoding() {
+    use prometheus_client::{
+        encoding::{text::encode, EncodeMetric, MetricEncoder, NoLabelSet},
+        metrics::{MetricType, TypedMetric},
+        registry::Registry,
+    };
+
+    // encodes through an intermediate `Vec`, without buffer reuse
+    #[derive(Debug)]
+    struct Unbuffered(Histogram);
+    impl TypedMetric for Unbuffered {
+        const TYPE: MetricType = MetricType::Histogram;
+    }
+    impl EncodeMetric for Unbuffered {
+        fn encode(&self, mut encoder: MetricEncoder) -> Result<(), std::fmt::Error> {
+            let (count, sum, buckets) = self.0.collect();
+            encoder.encode_histogram::<NoLabelSet>(sum, count, &buckets.collect_vec(), None)
+        }
+        fn metric_type(&self) -> MetricType {
+            MetricType::Histogram
+        }
+    }
+
+    let check = |boundaries: Vec<f64>, values: &[f64]| {
+        let histogram = Histogram::new(boundaries.clone());
+        let unbuffered = Histogram::new(boundaries);
+        for value in values {
+            histogram.observe(*value);
+            unbuffered.observe(*value);
+        }
+        let mut registry = Registry::default();
+        registry.register("buffered", "", histogram);
+        registry.register("unbuffered", "", Unbuffered(unbuffered));
+        let mut output = String::new();
+        encode(&mut output, &registry).unwrap();
+        let (buffered, unbuffered) = output.split_once("# HELP unbuffered").unwrap();
+        let unbuffered = unbuffered.split_once("# EOF").unwrap().0;
+        assert_eq!(
+            buffered.lines().skip(1).collect_vec().join("\n"),
+            unbuffered
+                .lines()
+                .skip(1)
+                .collect_vec()
+                .join("\n")
+                .replace("unbuffered", "buffered")
+        );
+    };
+    check((0..1000).map(f64::from).collect(), &[0.5, 10.0, 2000.0]);
+    // the reused buffer is larger than the second histogram
+    check(vec![1.0, 2.0], &[0.5, 1.5, 3.0]);
+}
+
+#[cfg(all(feature = "ffi", not(loom)))]
+#[test]
+#[allow(unsafe_code)]
+fn ffi() {
+    use std::ptr;
+
+    use crate::ffi::{histogram_collect, histogram_free, histogram_new, histogram_observe};
+
+    unsafe {
+        assert!(histogram_new(ptr::null(), 0).is_null());
+        assert!(histogram_new([2.0, 1.0].as_ptr(), 2).is_null());
+        let histogram = histogram_new([1.0, 2.0].as_ptr(), 2);
+        assert!(!histogram.is_null());
+        for value in [0.5, 1.5, 1.5, 3.0] {
+            histogram_observe(histogram, value);
+        }
+        histogram_observe(ptr::null(), 1.0);
+        let (mut counts, mut sum, mut count) = ([0; 3], 0.0, 0);
+        let collect = |counts: &mut [u64], sum: &mut f64, count: &mut u64| {
+            histogram_collect(histogram, counts.as_mut_ptr(), counts.len(), sum, count)
+        };
+        assert_eq!(collect(&mut counts[..2], &mut sum, &mut count), -2);
+        assert_eq!(collect(&mut counts, &mut sum, &mut count), 3);
+        assert_eq!((counts, sum, count), ([1, 2, 1], f64_sum(6.5), 4));
+        let null_count = ptr::null_mut();
+        let res = histogram_collect(histogram, counts.as_mut_ptr(), 3, &mut sum, null_count);
+        assert_eq!(res, -1);
+        histogram_free(histogram);
+        histogram_free(ptr::null_mut());
+    }
+}
@@ -0,0 +1,62 @@
+use std::fmt::Write;
+
+use crate::{Histogram, HistogramBuckets};
+
+/// Collects the histogram and asserts its buckets and sum match the expected ones.
+///
+/// # Panics
+///
+/// Panics with a bucket-by-bucket diff on mismatch.
+#[track_caller]
+pub fn assert_histogram<B: HistogramBuckets, const TRUSTED_BUCKETS: bool>(
+    histogram: &Histogram<B, TRUSTED_BUCKETS>,
+    expected: &[(f64, u64)],
+    sum: f64,
+) {
+    let (_, actual_sum, buckets) = histogram.collect();
+    if let Some(diff) = histogram_diff(&buckets.collect::<Vec<_>>(), actual_sum, expected, sum) {
+        panic!("{diff}");
+    }
+}
+
+fn histogram_diff(
+    actual: &[(f64, u64)],
+    actual_sum: f64,
+    expected: &[(f64, u64)],
+    expected_sum: f64,
+) -> Option<String> {
+    let sum_eq = actual_sum == expected_sum || (actual_sum.is_nan() && expected_sum.is_nan());
+    if actual == expected && sum_eq {
+        return None;
+    }
+    let fmt_bucket = |bucket: Option<&(f64, u64)>| match bucket {
+        Some((le, count)) => (fmt_le(*le), count.to_string()),
+        None => ("-".into(), "-".into()),
+    };
+    let mut diff = String::from("histogram mismatch (expected | actual):\n");
+    for i in 0..actual.len().max(expected.len()) {
+        let (expected_le, expected_count) = fmt_bucket(expected.get(i));
+        let (actual_le, actual_count) = fmt_bucket(actual.get(i));
+        let marker = if expected.get(i) == actual.get(i) {
+            ' '
+        } else {
+            '>'
+        };
+        writeln!(
+            diff,
+            "{marker} le={expected_le:<10} {expected_count:>10} | le={actual_le:<10} {actual_count:>10}"
+        )
+        .unwrap();
+    }
+    let marker = if sum_eq { ' ' } else { '>' };
+    write!(diff, "{marker} sum={expected_sum} | sum={actual_sum}").unwrap();
+    Some(diff)
+}
+
+fn fmt_le(le: f64) -> String {
+    if le == f64::INFINITY {
+        "+Inf".into()
+    } else {
+        le.to_string()
+    }
+}
@@ -0,0 +1,149 @@
+use std::{error, fmt};
+
+use crate::{Histogram, HistogramBuckets, HistogramSnapshot};
+
+/// Formatting of the `le` label of bucket boundaries, used by
+/// [`Histogram::write_text`].
+///
+/// Infinite boundaries are always formatted as `+Inf` or `-Inf`. `prometheus-client`
+/// encoding formats boundaries itself, so it ignores the histogram `LeFormat`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LeFormat {
+    /// Shortest representation, e.g. `0.005` or `1.0`.
+    #[default]
+    Shortest,
+    /// Fixed precision, e.g. `0.005000` for a precision of 6.
+    Fixed(usize),
+}
+
+impl LeFormat {
+    pub fn format(&self, le: f64) -> String {
+        match self {
+            _ if le.is_infinite() => format_value(le),
+            Self::Shortest => format!("{le:?}"),
+            Self::Fixed(precision) => format!("{le:.precision$}"),
+        }
+    }
+}
+
+/// Formats a sample value, with the Prometheus spelling of non-finite values.
+fn format_value(value: f64) -> String {
+    match value {
+        _ if value.is_nan() => "NaN".into(),
+        f64::INFINITY => "+Inf".into(),
+        f64::NEG_INFINITY => "-Inf".into(),
+        _ => format!("{value:?}"),
+    }
+}
+
+impl<B: HistogramBuckets, const TRUSTED_BUCKETS: bool> Histogram<B, TRUSTED_BUCKETS> {
+    /// Collects the histogram and writes it in Prometheus text exposition format.
+    ///
+    /// Boundaries are formatted according to the histogram [`LeFormat`]. It is
+    /// not used by `prometheus-client` encoding, which formats boundaries itself.
+    pub fn write_text(&self, writer: &mut impl fmt::Write, name: &str) -> fmt::Result {
+        let (count, sum, buckets) = self.collect();
+        writeln!(writer, "# TYPE {name} histogram")?;
+        let mut cumulative = 0;
+        for (le, bucket) in buckets {
+            cumulative += bucket;
+            let le = self.0.options.le_format.format(le);
+            writeln!(writer, "{name}_bucket{{le=\"{le}\"}} {cumulative}")?;
+        }
+        writeln!(writer, "{name}_sum {}", format_value(sum))?;
+        writeln!(writer, "{name}_count {count}")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    /// A line of the histogram is malformed, with its 1-based number.
+    InvalidLine(usize),
+    /// The `+Inf` bucket, the sum or the count is missing.
+    MissingSeries,
+    /// Cumulative bucket counts are decreasing, or exceed the count.
+    NotCumulative,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidLine(line) => write!(f, "invalid histogram line {line}"),
+            Self::MissingSeries => write!(f, "missing histogram bucket, sum or count"),
+            Self::NotCumulative => write!(f, "bucket counts are not cumulative"),
+        }
+    }
+}
+
+impl error::Error for ParseError {}
+
+impl HistogramSnapshot {
+    /// Parses the histogram `name` from a Prometheus text exposition, e.g. written by
+    /// [`Histogram::write_text`].
+    ///
+    /// Lines of other metrics are ignored, and bucket lines may be in any order.
+    /// Cumulative bucket counts are converted back to per-bucket counts.
+    pub fn parse_prometheus_text(text: &str, name: &str) -> Result<Self, ParseError> {
+        let (mut cumulative, mut sum, mut count) = (Vec::new(), None, None);
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || ParseError::InvalidLine(i + 1);
+            // label values may contain spaces, so labels are split first
+            let (series, value) = match line.find('}') {
+                Some(end) => line.split_at(end + 1),
+                None => line.split_once(char::is_whitespace).ok_or_else(invalid)?,
+            };
+            let (metric, labels) = series.split_once('{').unwrap_or((series, ""));
+            let Some(suffix) = metric.strip_prefix(name) else {
+                continue;
+            };
+            // the timestamp, if any, is ignored
+            let value = value.split_whitespace().next().ok_or_else(invalid)?;
+            match suffix {
+                "_bucket" => {
+                    let le = labels
+                        .split(',')
+                        .find_map(|label| label.trim().strip_prefix("le="))
+                        .and_then(|le| le.trim_end_matches('}').strip_prefix('"'))
+                        .and_then(|le| le.strip_suffix('"'))
+                        .ok_or_else(invalid)?;
+                    let le = match le {
+                        "+Inf" => f64::INFINITY,
+                        le => le.parse().map_err(|_| invalid())?,
+                    };
+                    cumulative.push((le, value.parse::<u64>().map_err(|_| invalid())?));
+                }
+                "_sum" => sum = Some(value.parse::<f64>().map_err(|_| invalid())?),
+                "_count" => count = Some(value.parse::<u64>().map_err(|_| invalid())?),
+                _ => continue,
+            }
+        }
+        let (Some(sum), Some(count)) = (sum, count) else {
+            return Err(ParseError::MissingSeries);
+        };
+        cumulative.sort_by(|(le1, _), (le2, _)| le1.total_cmp(le2));
+        if cumulative.last().is_none_or(|(le, _)| *le != f64::INFINITY) {
+            return Err(ParseError::MissingSeries);
+        }
+        let mut previous = 0;
+        let mut buckets = Vec::with_capacity(cumulative.len());
+        for (le, cumulative) in cumulative {
+            let bucket = cumulative
+                .checked_sub(previous)
+                .ok_or(ParseError::NotCumulative)?;
+            buckets.push((le, bucket));
+            previous = cumulative;
+        }
+        if previous > count {
+            return Err(ParseError::NotCumulative);
+        }
+        Ok(Self {
+            count,
+            sum,
+            buckets,
+        })
+    }
+}
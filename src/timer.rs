@@ -0,0 +1,99 @@
+use std::time::{Duration, Instant};
+
+use crate::{Histogram, HistogramBuckets};
+
+/// Source of time for [`HistogramTimer`], which can be mocked in tests.
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+impl<C: Clock + ?Sized> Clock for &C {
+    fn now(&self) -> Instant {
+        (**self).now()
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+impl<B: HistogramBuckets<Value = Duration>, const TRUSTED_BUCKETS: bool>
+    Histogram<B, TRUSTED_BUCKETS>
+{
+    /// Observes the duration elapsed between `start` and `now`, or zero if `now` is earlier.
+    pub fn observe_at(&self, start: Instant, now: Instant) {
+        self.observe(now.saturating_duration_since(start));
+    }
+
+    /// Observes the duration elapsed since `start`.
+    pub fn observe_duration_since(&self, start: Instant) {
+        self.observe(start.elapsed());
+    }
+
+    pub fn start_timer(&self) -> HistogramTimer<'_, B, SystemClock, TRUSTED_BUCKETS> {
+        self.start_timer_with_clock(SystemClock)
+    }
+
+    pub fn start_timer_with_clock<C: Clock>(
+        &self,
+        clock: C,
+    ) -> HistogramTimer<'_, B, C, TRUSTED_BUCKETS> {
+        HistogramTimer {
+            histogram: self,
+            start: clock.now(),
+            clock,
+            observed: false,
+        }
+    }
+}
+
+/// Timer observing the elapsed duration when dropped.
+#[derive(Debug)]
+#[must_use = "the timer observes the elapsed duration when dropped"]
+pub struct HistogramTimer<
+    'a,
+    B: HistogramBuckets<Value = Duration>,
+    C: Clock = SystemClock,
+    const TRUSTED_BUCKETS: bool = false,
+> {
+    histogram: &'a Histogram<B, TRUSTED_BUCKETS>,
+    clock: C,
+    start: Instant,
+    observed: bool,
+}
+
+impl<B: HistogramBuckets<Value = Duration>, C: Clock, const TRUSTED_BUCKETS: bool>
+    HistogramTimer<'_, B, C, TRUSTED_BUCKETS>
+{
+    /// Observes the elapsed duration and returns it.
+    pub fn observe_duration(mut self) -> Duration {
+        self.observe()
+    }
+
+    /// Stops the timer without observing.
+    pub fn stop_and_discard(mut self) {
+        self.observed = true;
+    }
+
+    fn observe(&mut self) -> Duration {
+        self.observed = true;
+        let elapsed = self.clock.now().saturating_duration_since(self.start);
+        self.histogram.observe(elapsed);
+        elapsed
+    }
+}
+
+impl<B: HistogramBuckets<Value = Duration>, C: Clock, const TRUSTED_BUCKETS: bool> Drop
+    for HistogramTimer<'_, B, C, TRUSTED_BUCKETS>
+{
+    fn drop(&mut self) {
+        if !self.observed {
+            self.observe();
+        }
+    }
+}
@@ -0,0 +1,13 @@
+use crate::{Histogram, HistogramBuckets};
+
+impl<B: HistogramBuckets, const TRUSTED_BUCKETS: bool> Histogram<B, TRUSTED_BUCKETS> {
+    /// Collects the histogram and emits it as a `tracing` event.
+    ///
+    /// The event has `name`, `count` and `sum` fields, and a `buckets` field with the
+    /// `(boundary, count)` pairs formatted with [`Debug`].
+    pub fn trace_collect(&self, name: &str) {
+        let (count, sum, buckets) = self.collect();
+        let buckets = buckets.collect::<Vec<_>>();
+        tracing::info!(name, count, sum, buckets = ?buckets, "histogram collected");
+    }
+}
@@ -0,0 +1,9 @@
+#![deny(unused_must_use)]
+
+use histogram::Histogram;
+
+fn main() {
+    let histogram = Histogram::new(vec![1.0]);
+    histogram.collect();
+    histogram.snapshot();
+}
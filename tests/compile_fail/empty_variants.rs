@@ -0,0 +1,18 @@
+use histogram::{Categorical, EnumBuckets, Histogram};
+
+#[derive(Clone, Copy)]
+enum Never {}
+
+impl Categorical for Never {
+    const VARIANTS: &'static [Self] = &[];
+    fn index(&self) -> usize {
+        match *self {}
+    }
+}
+
+// evaluated at compile time, so that `cargo check` reports it too
+const BUCKETS: EnumBuckets<Never> = EnumBuckets::new();
+
+fn main() {
+    let _ = Histogram::new(BUCKETS);
+}
@@ -0,0 +1,30 @@
+/* Round trip through the C API, built against the `ffi` staticlib, see CI. */
+#include <assert.h>
+#include <stddef.h>
+
+#include "histogram.h"
+
+int main(void) {
+    double invalid[] = {2.0, 1.0};
+    assert(histogram_new(invalid, 2) == NULL);
+
+    double boundaries[] = {1.0, 2.0};
+    Histogram *histogram = histogram_new(boundaries, 2);
+    assert(histogram != NULL);
+    histogram_observe(histogram, 0.5);
+    histogram_observe(histogram, 1.5);
+    histogram_observe(histogram, 3.0);
+
+    uint64_t counts[3];
+    double sum;
+    uint64_t count;
+    assert(histogram_collect(histogram, counts, 2, &sum, &count) == -2);
+    assert(histogram_collect(histogram, counts, 3, NULL, &count) == -1);
+    assert(histogram_collect(histogram, counts, 3, &sum, &count) == 3);
+    assert(counts[0] == 1 && counts[1] == 1 && counts[2] == 1);
+    assert(sum == 5.0 && count == 3);
+
+    histogram_free(histogram);
+    histogram_free(NULL);
+    return 0;
+}
@@ -0,0 +1,5 @@
+#[test]
+#[cfg_attr(miri, ignore)]
+fn must_use() {
+    trybuild::TestCases::new().compile_fail("tests/compile_fail/*.rs");
+}